@@ -1,3 +1,6 @@
+PHYS_OFFSET equ 0xffff800000000000                                   ; direct map of physical memory
+DIRECT_MAP_PDS equ 4                                                 ; 4 GiB of 2MiB pages
+
     section .multiboot
     align 8
 mb2_header_start:
@@ -12,47 +15,85 @@ mb2_header_start:
     dd 8
 mb2_header_end:
 
-    section .bss
-    align 4096
+; Boot page tables live in low memory so the trampoline can reach them before paging is on
+    section .boot.bss nobits alloc noexec write align=4096
 pml4:
     resb 4096
-pdpt:
+pdpt_phys:
     resb 4096
-pd:
+pdpt_kernel:
     resb 4096
+pd_phys:
+    resb 4096 * DIRECT_MAP_PDS
+pd_kernel:
+    resb 4096
+
+    section .bss
+    align 4096
+    global stack_guard
+stack_guard:
+    resb 4096                                                        ; left unmapped, overflowing the stack faults here
 stack:
     resb 16384
 stack_top:
 
-    section .text
+    section .boot.text progbits alloc exec nowrite align=16
     bits 32
     global _start
     extern main
 
 _start:
-    mov esp, stack_top
+    mov edi, eax                                                     ; multiboot2 magic, 1st argument to main
+    mov esi, ebx                                                     ; multiboot2 info pointer, 2nd argument to main
 
 ; Build page tables
-; PML4[0] -> PDPT
-    mov eax, pdpt
+; PML4[0] (identity) and PML4[256] (direct map) -> PDPT covering the first 4 GiB
+    mov eax, pdpt_phys
     or eax, 0x03                                                     ; present + writable
     mov [pml4], eax
+    mov [pml4 + 256 * 8], eax
 
-; PDPT[0] -> PD
-    mov eax, pd
+; PML4[511] -> kernel PDPT
+    mov eax, pdpt_kernel
     or eax, 0x03
-    mov [pdpt], eax
+    mov [pml4 + 511 * 8], eax
 
-; Identity map first 1GiB using 2MiB pages
+; PDPT_phys[0..4] -> direct map PDs
+    mov ecx, 0
+    mov eax, pd_phys
+    or eax, 0x03
+.map_pdpt:
+    mov [pdpt_phys + ecx * 8], eax
+    add eax, 4096
+    inc ecx
+    cmp ecx, DIRECT_MAP_PDS
+    jne .map_pdpt
+
+; PDPT_kernel[510] -> kernel PD, 0xffffffff80000000 is the 510th GiB of the last PML4 slot
+    mov eax, pd_kernel
+    or eax, 0x03
+    mov [pdpt_kernel + 510 * 8], eax
+
+; Map the first 4 GiB using 2MiB pages
     mov ecx, 0
     mov eax, 0x83                                                    ; present + writable + huge page
 .map_pd:
-    mov [pd + ecx * 8], eax
+    mov [pd_phys + ecx * 8], eax
     add eax, 0x200000                                                ; 2MiB
     inc ecx
-    cmp ecx, 512
+    cmp ecx, 512 * DIRECT_MAP_PDS
     jne .map_pd
 
+; Map the first GiB again into the kernel window
+    mov ecx, 0
+    mov eax, 0x83
+.map_kernel_pd:
+    mov [pd_kernel + ecx * 8], eax
+    add eax, 0x200000
+    inc ecx
+    cmp ecx, 512
+    jne .map_kernel_pd
+
 ; Enable long mode
     mov eax, pml4
     mov cr3, eax                                                     ; load page table
@@ -71,24 +112,44 @@ _start:
     mov cr0, eax
 
     lgdt [gdt.ptr]                                                   ; load GDT
-    jmp 0x08:long_mode                                               ; far jump to 64-bit code
+    jmp 0x08:long_mode_low                                           ; far jump to 64-bit code
 
-    section .rodata
+    section .boot.rodata progbits alloc noexec nowrite align=8
 gdt:
     dq 0                                                             ; null descriptor
     dq 0x00209A0000000000                                            ; code segment
 .ptr:
-    dw $ - gdt - 1
+    dw .ptr - gdt - 1
     dq gdt
+.ptr_high:
+    dw .ptr - gdt - 1
+    dq gdt + PHYS_OFFSET                                             ; same GDT, reached through the direct map
+
+    section .boot.text
+    bits 64
+long_mode_low:
+    mov rax, long_mode
+    jmp rax                                                          ; jump into the higher half
 
     section .text
     bits 64
 long_mode:
+    lgdt [gdt.ptr_high]                                              ; the low alias is about to disappear
     xor ax, ax
     mov ss, ax
     mov ds, ax
     mov es, ax
 
+    mov rsp, stack_top
+
+; Drop the identity map, the kernel only runs from the higher half from here on
+    mov rax, pml4 + PHYS_OFFSET
+    mov qword [rax], 0
+    mov rax, cr3
+    mov cr3, rax                                                     ; flush the TLB
+
+    mov edi, edi                                                     ; upper halves are undefined after the
+    mov esi, esi                                                     ; mode switch, zero-extend both arguments
     call main
 
 .halt:
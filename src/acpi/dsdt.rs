@@ -0,0 +1,104 @@
+/// Values for the SLP_TYP fields of the PM1a and PM1b control registers that select a sleep
+/// state, here S5, soft off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SleepType {
+    pub a: u16,
+    pub b: u16,
+}
+
+// AML opcodes around the `\_S5_` package
+const NAME_OP: u8 = 0x08;
+const ROOT_PREFIX: u8 = b'\\';
+const PACKAGE_OP: u8 = 0x12;
+const ZERO_OP: u8 = 0x00;
+const ONE_OP: u8 = 0x01;
+const BYTE_PREFIX: u8 = 0x0a;
+const WORD_PREFIX: u8 = 0x0b;
+const DWORD_PREFIX: u8 = 0x0c;
+
+/// Digs the S5 sleep type out of the DSDT's AML without interpreting it. This only works because
+/// firmware practically always declares `Name (_S5, Package () { a, b, ... })` with constant
+/// values, which is all the kernel needs for shutting down.
+pub fn find_s5(aml: &[u8]) -> Option<SleepType> {
+    let at = aml.windows(4).enumerate().find_map(|(at, name)| {
+        let declared = match at {
+            0 => false,
+            1 => aml[0] == NAME_OP,
+            _ => aml[at - 1] == NAME_OP || (aml[at - 1] == ROOT_PREFIX && aml[at - 2] == NAME_OP),
+        };
+        (name == b"_S5_" && declared).then_some(at + 4)
+    })?;
+
+    let mut bytes = aml.get(at..)?.iter().copied();
+    if bytes.next()? != PACKAGE_OP {
+        return None;
+    }
+    // the top two bits of the first PkgLength byte say how many more bytes follow
+    let lead = bytes.next()?;
+    for _ in 0..lead >> 6 {
+        bytes.next()?;
+    }
+    let _elements = bytes.next()?;
+
+    // anything but a constant, like a name to look up, would need a real AML interpreter
+    let mut integer = || match bytes.next()? {
+        BYTE_PREFIX => bytes.next().map(u16::from),
+        WORD_PREFIX => Some(u16::from_le_bytes([bytes.next()?, bytes.next()?])),
+        DWORD_PREFIX => {
+            let value = [bytes.next()?, bytes.next()?, bytes.next()?, bytes.next()?];
+            u16::try_from(u32::from_le_bytes(value)).ok()
+        }
+        ZERO_OP => Some(0),
+        ONE_OP => Some(1),
+        _ => None,
+    };
+    let a = integer()?;
+    let b = integer()?;
+    Some(SleepType { a, b })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn finds_the_s5_package() {
+        // Name (\_S5, Package (0x04) { 0x05, Zero, Zero, Zero }) somewhere in the middle
+        let aml = [
+            0x10, 0x42, 0x08, b'\\', b'_', b'S', b'5', b'_', 0x12, 0x0a, 0x04, 0x0a, 0x05, 0x00,
+            0x00, 0x00, 0x14, 0x08,
+        ];
+        assert_eq!(find_s5(&aml), Some(SleepType { a: 5, b: 0 }));
+
+        // QEMU's, with a one byte PkgLength and no root prefix
+        let aml = [
+            NAME_OP, b'_', b'S', b'5', b'_', 0x12, 0x06, 0x04, 0x00, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(find_s5(&aml), Some(SleepType { a: 0, b: 0 }));
+    }
+
+    #[test_case]
+    fn ignores_references_that_are_not_declarations() {
+        // a method calling _S5_ rather than naming it
+        let aml = [
+            0x14, 0x0a, b'_', b'S', b'5', b'_', 0x12, 0x06, 0x04, 0x0a, 0x05,
+        ];
+        assert_eq!(find_s5(&aml), None);
+    }
+
+    #[test_case]
+    fn only_constant_values_are_read() {
+        // Package (0x02) { Word 0x0007, DWord 0x00000007 }
+        let aml = [
+            NAME_OP, b'_', b'S', b'5', b'_', 0x12, 0x0b, 0x02, 0x0b, 0x07, 0x00, 0x0c, 0x07, 0x00,
+            0x00, 0x00,
+        ];
+        assert_eq!(find_s5(&aml), Some(SleepType { a: 7, b: 7 }));
+
+        // the first value is a name, which can't be resolved here
+        let aml = [
+            NAME_OP, b'_', b'S', b'5', b'_', 0x12, 0x0a, 0x02, b'S', b'L', b'P', b'5', 0x00,
+        ];
+        assert_eq!(find_s5(&aml), None);
+    }
+}
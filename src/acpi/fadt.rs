@@ -0,0 +1,125 @@
+use x86_64::PhysAddr;
+
+use super::{GenericAddress, u16_at, u32_at, u64_at};
+
+// byte offsets from the start of the table, header included, as in the spec
+const DSDT: usize = 40;
+const SCI_INTERRUPT: usize = 46;
+const SMI_COMMAND: usize = 48;
+const ACPI_ENABLE: usize = 52;
+const ACPI_DISABLE: usize = 53;
+const PM1A_EVENT_BLOCK: usize = 56;
+const PM1B_EVENT_BLOCK: usize = 60;
+const PM1A_CONTROL_BLOCK: usize = 64;
+const PM1B_CONTROL_BLOCK: usize = 68;
+const PM_TIMER_BLOCK: usize = 76;
+const FLAGS: usize = 112;
+// everything from here on is missing in ACPI 1.0 tables
+const RESET_REGISTER: usize = 116;
+const RESET_VALUE: usize = 128;
+const X_DSDT: usize = 140;
+
+const FLAG_RESET_REGISTER_SUPPORTED: u32 = 1 << 10;
+
+/// The Fixed ACPI Description Table, signature `FACP`. Mostly I/O ports of the power management
+/// registers, a port of 0 means the block doesn't exist.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fadt {
+    pub dsdt: PhysAddr,
+    pub sci_interrupt: u16,
+    /// Writing `acpi_enable` here hands the power management registers from SMM to the OS.
+    pub smi_command_port: u32,
+    pub acpi_enable: u8,
+    pub acpi_disable: u8,
+    pub pm1a_event_block: u32,
+    pub pm1b_event_block: u32,
+    pub pm1a_control_block: u32,
+    pub pm1b_control_block: u32,
+    pub pm_timer_block: u32,
+    pub flags: u32,
+    /// Writing `value` to `register` resets the machine.
+    pub reset: Option<ResetRegister>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResetRegister {
+    pub register: GenericAddress,
+    pub value: u8,
+}
+
+impl Fadt {
+    /// Parses a whole FADT, header included.
+    pub fn parse(bytes: &[u8]) -> Self {
+        // fields past the end of an older, shorter table read as zero
+        let mut table = [0; X_DSDT + 8];
+        let len = bytes.len().min(table.len());
+        table[..len].copy_from_slice(&bytes[..len]);
+
+        let flags = u32_at(&table, FLAGS);
+        let reset = (flags & FLAG_RESET_REGISTER_SUPPORTED != 0 && len > RESET_VALUE).then(|| {
+            ResetRegister {
+                register: GenericAddress::parse(&table[RESET_REGISTER..]),
+                value: table[RESET_VALUE],
+            }
+        });
+
+        let dsdt = match u64_at(&table, X_DSDT) {
+            0 => u32_at(&table, DSDT) as u64,
+            x_dsdt => x_dsdt,
+        };
+
+        Self {
+            dsdt: PhysAddr::new(dsdt),
+            sci_interrupt: u16_at(&table, SCI_INTERRUPT),
+            smi_command_port: u32_at(&table, SMI_COMMAND),
+            acpi_enable: table[ACPI_ENABLE],
+            acpi_disable: table[ACPI_DISABLE],
+            pm1a_event_block: u32_at(&table, PM1A_EVENT_BLOCK),
+            pm1b_event_block: u32_at(&table, PM1B_EVENT_BLOCK),
+            pm1a_control_block: u32_at(&table, PM1A_CONTROL_BLOCK),
+            pm1b_control_block: u32_at(&table, PM1B_CONTROL_BLOCK),
+            pm_timer_block: u32_at(&table, PM_TIMER_BLOCK),
+            flags,
+            reset,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acpi::AddressSpace;
+
+    #[test_case]
+    fn acpi_1_tables_have_no_reset_register() {
+        let mut bytes = [0u8; RESET_REGISTER];
+        bytes[DSDT..DSDT + 4].copy_from_slice(&0x7fe_0040u32.to_le_bytes());
+        bytes[PM1A_CONTROL_BLOCK..PM1A_CONTROL_BLOCK + 4].copy_from_slice(&0x604u32.to_le_bytes());
+        bytes[FLAGS..FLAGS + 4].copy_from_slice(&FLAG_RESET_REGISTER_SUPPORTED.to_le_bytes());
+
+        let fadt = Fadt::parse(&bytes);
+        assert_eq!(fadt.dsdt, PhysAddr::new(0x7fe_0040));
+        assert_eq!(fadt.pm1a_control_block, 0x604);
+        assert_eq!(fadt.reset, None);
+    }
+
+    #[test_case]
+    fn reset_register_and_x_dsdt_from_newer_tables() {
+        let mut bytes = [0u8; 244];
+        bytes[DSDT..DSDT + 4].copy_from_slice(&0x1000u32.to_le_bytes());
+        bytes[X_DSDT..X_DSDT + 8].copy_from_slice(&0x2000u64.to_le_bytes());
+        bytes[FLAGS..FLAGS + 4].copy_from_slice(&FLAG_RESET_REGISTER_SUPPORTED.to_le_bytes());
+        // the PIIX reset control register, port 0xcf9
+        bytes[RESET_REGISTER] = 1;
+        bytes[RESET_REGISTER + 1] = 8;
+        bytes[RESET_REGISTER + 4..RESET_REGISTER + 12].copy_from_slice(&0xcf9u64.to_le_bytes());
+        bytes[RESET_VALUE] = 0x0e;
+
+        let fadt = Fadt::parse(&bytes);
+        assert_eq!(fadt.dsdt, PhysAddr::new(0x2000));
+        let reset = fadt.reset.unwrap();
+        assert_eq!(reset.register.space, AddressSpace::SystemIo);
+        assert_eq!(reset.register.address, 0xcf9);
+        assert_eq!(reset.value, 0x0e);
+    }
+}
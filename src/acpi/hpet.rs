@@ -0,0 +1,72 @@
+use x86_64::PhysAddr;
+
+use super::{AddressSpace, GenericAddress, u16_at, u32_at};
+
+const EVENT_TIMER_BLOCK_ID: usize = 36;
+const BASE_ADDRESS: usize = 40;
+const HPET_NUMBER: usize = 52;
+const MINIMUM_TICK: usize = 53;
+const TABLE_SIZE: usize = 56;
+
+/// The HPET Description Table, signature `HPET`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hpet {
+    pub address: PhysAddr,
+    pub number: u8,
+    pub comparators: u8,
+    pub counter_64bit: bool,
+    /// Can take over IRQ 0 and 8 from the PIT and RTC.
+    pub legacy_replacement: bool,
+    /// Smallest periodic tick, in main counter ticks, that won't lose interrupts.
+    pub minimum_tick: u16,
+}
+
+impl Hpet {
+    /// Parses a whole HPET table, `None` when it is cut short or the registers aren't memory
+    /// mapped.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < TABLE_SIZE {
+            return None;
+        }
+
+        let base = GenericAddress::parse(&bytes[BASE_ADDRESS..]);
+        if base.space != AddressSpace::SystemMemory {
+            return None;
+        }
+
+        let id = u32_at(bytes, EVENT_TIMER_BLOCK_ID);
+        Some(Self {
+            address: PhysAddr::new(base.address),
+            number: bytes[HPET_NUMBER],
+            comparators: ((id >> 8) & 0x1f) as u8 + 1,
+            counter_64bit: id & (1 << 13) != 0,
+            legacy_replacement: id & (1 << 15) != 0,
+            minimum_tick: u16_at(bytes, MINIMUM_TICK),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn parses_the_event_timer_block() {
+        let mut bytes = [0u8; TABLE_SIZE];
+        // vendor 0x8086, legacy capable, 64 bit, 3 comparators, revision 1
+        bytes[EVENT_TIMER_BLOCK_ID..EVENT_TIMER_BLOCK_ID + 4]
+            .copy_from_slice(&0x8086_a201u32.to_le_bytes());
+        bytes[BASE_ADDRESS + 4..BASE_ADDRESS + 12].copy_from_slice(&0xfed0_0000u64.to_le_bytes());
+        bytes[MINIMUM_TICK..MINIMUM_TICK + 2].copy_from_slice(&128u16.to_le_bytes());
+
+        let hpet = Hpet::parse(&bytes).unwrap();
+        assert_eq!(hpet.address, PhysAddr::new(0xfed0_0000));
+        assert_eq!(hpet.comparators, 3);
+        assert!(hpet.counter_64bit && hpet.legacy_replacement);
+        assert_eq!(hpet.minimum_tick, 128);
+
+        // an I/O port HPET is not something we can drive
+        bytes[BASE_ADDRESS] = 1;
+        assert_eq!(Hpet::parse(&bytes), None);
+    }
+}
@@ -0,0 +1,183 @@
+use alloc::vec::Vec;
+
+use x86_64::PhysAddr;
+
+use super::{u16_at, u32_at, u64_at};
+
+const ENTRY_LOCAL_APIC: u8 = 0;
+const ENTRY_IO_APIC: u8 = 1;
+const ENTRY_INTERRUPT_OVERRIDE: u8 = 2;
+const ENTRY_LOCAL_APIC_ADDRESS: u8 = 5;
+
+// the local APIC address and the flags come before the entries
+const ENTRIES_OFFSET: usize = 8;
+
+// MADT flags, the machine also has the two 8259s wired up
+const PCAT_COMPAT: u32 = 1 << 0;
+
+// local APIC flags, a processor that is neither can't be used at all
+const PROCESSOR_ENABLED: u32 = 1 << 0;
+const PROCESSOR_ONLINE_CAPABLE: u32 = 1 << 1;
+
+/// The Multiple APIC Description Table, signature `APIC`.
+#[derive(Debug, Clone)]
+pub struct Madt {
+    pub local_apic_address: PhysAddr,
+    pub processors: Vec<Processor>,
+    pub io_apics: Vec<IoApicEntry>,
+    pub overrides: Vec<InterruptOverride>,
+    pub has_legacy_pics: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Processor {
+    pub processor_id: u8,
+    pub apic_id: u8,
+    /// Running now, otherwise it can only be brought up later.
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoApicEntry {
+    pub id: u8,
+    pub address: PhysAddr,
+    /// First global system interrupt this I/O APIC handles.
+    pub gsi_base: u32,
+}
+
+/// An ISA IRQ that is not wired to the global system interrupt of the same number, or not with
+/// the ISA default of active high, edge triggered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterruptOverride {
+    pub irq: u8,
+    pub gsi: u32,
+    pub active_low: bool,
+    pub level_triggered: bool,
+}
+
+impl Madt {
+    /// Parses the part of a MADT after the common table header, `None` when it is cut short.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < ENTRIES_OFFSET {
+            return None;
+        }
+
+        let mut madt = Self {
+            local_apic_address: PhysAddr::new(u32_at(data, 0) as u64),
+            processors: Vec::new(),
+            io_apics: Vec::new(),
+            overrides: Vec::new(),
+            has_legacy_pics: u32_at(data, 4) & PCAT_COMPAT != 0,
+        };
+
+        let mut entries = &data[ENTRIES_OFFSET..];
+        while let [kind, len, ..] = *entries {
+            let len = len as usize;
+            if len < 2 || len > entries.len() {
+                break;
+            }
+            let entry = &entries[..len];
+            entries = &entries[len..];
+
+            match kind {
+                ENTRY_LOCAL_APIC if len >= 8 => {
+                    let flags = u32_at(entry, 4);
+                    if flags & (PROCESSOR_ENABLED | PROCESSOR_ONLINE_CAPABLE) != 0 {
+                        madt.processors.push(Processor {
+                            processor_id: entry[2],
+                            apic_id: entry[3],
+                            enabled: flags & PROCESSOR_ENABLED != 0,
+                        });
+                    }
+                }
+                ENTRY_IO_APIC if len >= 12 => madt.io_apics.push(IoApicEntry {
+                    id: entry[2],
+                    address: PhysAddr::new(u32_at(entry, 4) as u64),
+                    gsi_base: u32_at(entry, 8),
+                }),
+                ENTRY_INTERRUPT_OVERRIDE if len >= 10 => {
+                    // polarity and trigger mode, 0b11 is active low and level, 0b00 means the
+                    // bus default which for ISA is the opposite
+                    let flags = u16_at(entry, 8);
+                    madt.overrides.push(InterruptOverride {
+                        irq: entry[3],
+                        gsi: u32_at(entry, 4),
+                        active_low: flags & 0b11 == 0b11,
+                        level_triggered: (flags >> 2) & 0b11 == 0b11,
+                    });
+                }
+                ENTRY_LOCAL_APIC_ADDRESS if len >= 12 => {
+                    madt.local_apic_address = PhysAddr::new(u64_at(entry, 4));
+                }
+                _ => {}
+            }
+        }
+
+        Some(madt)
+    }
+
+    /// Global system interrupt an ISA IRQ arrives on, with its polarity and trigger mode.
+    pub fn isa_irq(&self, irq: u8) -> InterruptOverride {
+        self.overrides
+            .iter()
+            .copied()
+            .find(|o| o.irq == irq)
+            .unwrap_or(InterruptOverride {
+                irq,
+                gsi: irq as u32,
+                active_low: false,
+                level_triggered: false,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test_case]
+    fn parses_processors_io_apics_and_overrides() {
+        let mut data = vec![];
+        data.extend_from_slice(&0xfee0_0000u32.to_le_bytes());
+        data.extend_from_slice(&PCAT_COMPAT.to_le_bytes());
+        // one running processor and a disabled one that can never come up
+        data.extend_from_slice(&[ENTRY_LOCAL_APIC, 8, 0, 0, 1, 0, 0, 0]);
+        data.extend_from_slice(&[ENTRY_LOCAL_APIC, 8, 1, 1, 0, 0, 0, 0]);
+        // I/O APIC 0 at 0xfec00000 starting at GSI 0
+        data.extend_from_slice(&[ENTRY_IO_APIC, 12, 0, 0]);
+        data.extend_from_slice(&0xfec0_0000u32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        // the PIT is on GSI 2, and SCI on IRQ 9 is active high, level triggered
+        data.extend_from_slice(&[ENTRY_INTERRUPT_OVERRIDE, 10, 0, 0, 2, 0, 0, 0, 0, 0]);
+        data.extend_from_slice(&[ENTRY_INTERRUPT_OVERRIDE, 10, 0, 9, 9, 0, 0, 0, 0b1101, 0]);
+
+        let madt = Madt::parse(&data).unwrap();
+        assert_eq!(madt.local_apic_address, PhysAddr::new(0xfee0_0000));
+        assert!(madt.has_legacy_pics);
+        assert_eq!(
+            madt.processors,
+            [Processor {
+                processor_id: 0,
+                apic_id: 0,
+                enabled: true,
+            }]
+        );
+        assert_eq!(
+            madt.io_apics,
+            [IoApicEntry {
+                id: 0,
+                address: PhysAddr::new(0xfec0_0000),
+                gsi_base: 0,
+            }]
+        );
+
+        assert_eq!(madt.isa_irq(0).gsi, 2);
+        assert_eq!(madt.isa_irq(1).gsi, 1);
+        let sci = madt.isa_irq(9);
+        assert!(sci.level_triggered && !sci.active_low);
+
+        // no room for the flags
+        assert!(Madt::parse(&data[..6]).is_none());
+    }
+}
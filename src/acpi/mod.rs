@@ -0,0 +1,331 @@
+use core::{fmt, slice};
+
+use anyhow::Result;
+use spin::Once;
+use x86_64::PhysAddr;
+
+use crate::{
+    memory::{is_direct_mapped, phys_to_virt},
+    multiboot::BootInfo,
+};
+
+pub mod dsdt;
+pub mod fadt;
+pub mod hpet;
+pub mod madt;
+
+use dsdt::SleepType;
+use fadt::Fadt;
+use hpet::Hpet;
+use madt::Madt;
+
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+// ACPI 1.0 ends after the RSDT address, 2.0 adds the length, the XSDT address and a checksum
+// over the whole structure
+const RSDP_V1_SIZE: usize = 20;
+const RSDP_V2_SIZE: usize = 36;
+
+// every system description table starts with this header
+const SDT_HEADER_SIZE: usize = 36;
+
+// real mode segment of the EBDA, kept in the BIOS data area
+const EBDA_POINTER: u64 = 0x40e;
+const EBDA_SEARCH_SIZE: usize = 1024;
+const BIOS_AREA_START: u64 = 0xe_0000;
+const BIOS_AREA_END: u64 = 0x10_0000;
+
+static ACPI: Once<Acpi> = Once::new();
+
+/// The tables the kernel understands, parsed once at boot.
+#[derive(Debug)]
+pub struct Acpi {
+    pub rsdp: Rsdp,
+    pub madt: Option<Madt>,
+    pub fadt: Option<Fadt>,
+    pub hpet: Option<Hpet>,
+    /// From the DSDT the FADT points at.
+    pub s5: Option<SleepType>,
+}
+
+/// Finds and parses the ACPI tables, using the RSDP the bootloader copied when there is one.
+pub fn init(boot_info: &BootInfo) -> Result<&'static Acpi> {
+    let rsdp = match boot_info.rsdp() {
+        Some(bytes) => Rsdp::parse(bytes)?,
+        None => find_rsdp().ok_or_else(|| anyhow::anyhow!("No RSDP found"))?,
+    };
+
+    let find = |signature: &[u8; 4]| tables(&rsdp).find(|table| table.signature() == signature);
+    let fadt = find(b"FACP").map(|table| Fadt::parse(table.bytes));
+    // the DSDT is not listed in the RSDT, only the FADT knows where it is
+    let s5 = fadt
+        .filter(|fadt| !fadt.dsdt.is_null())
+        .and_then(|fadt| unsafe { Sdt::new(fadt.dsdt) })
+        .filter(|dsdt| dsdt.signature() == b"DSDT" && dsdt.is_valid())
+        .and_then(|dsdt| dsdt::find_s5(dsdt.data()));
+
+    let acpi = Acpi {
+        madt: find(b"APIC").and_then(|table| Madt::parse(table.data())),
+        fadt,
+        hpet: find(b"HPET").and_then(|table| Hpet::parse(table.bytes)),
+        s5,
+        rsdp,
+    };
+    Ok(ACPI.call_once(|| acpi))
+}
+
+impl fmt::Display for Acpi {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "revision {}", self.rsdp.revision)?;
+        if let Some(madt) = &self.madt {
+            write!(
+                f,
+                ", {} processors, {} I/O APICs",
+                madt.processors.len(),
+                madt.io_apics.len()
+            )?;
+        }
+        if self.fadt.is_some() {
+            write!(f, ", FADT")?;
+        }
+        if let Some(hpet) = &self.hpet {
+            write!(f, ", HPET at {:#x}", hpet.address.as_u64())?;
+        }
+        Ok(())
+    }
+}
+
+/// The tables parsed by [`init`], `None` when there are none.
+pub fn get() -> Option<&'static Acpi> {
+    ACPI.get()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rsdp {
+    pub revision: u8,
+    pub rsdt: PhysAddr,
+    /// Only from ACPI 2.0 on, preferred over the RSDT when present.
+    pub xsdt: Option<PhysAddr>,
+}
+
+impl Rsdp {
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < RSDP_V1_SIZE || &bytes[..8] != RSDP_SIGNATURE {
+            return Err(anyhow::anyhow!("Not an RSDP"));
+        }
+        if !checksum_ok(&bytes[..RSDP_V1_SIZE]) {
+            return Err(anyhow::anyhow!("Bad RSDP checksum"));
+        }
+
+        let revision = bytes[15];
+        let mut rsdp = Self {
+            revision,
+            rsdt: PhysAddr::new(u32_at(bytes, 16) as u64),
+            xsdt: None,
+        };
+        if revision >= 2 && bytes.len() >= RSDP_V2_SIZE {
+            let len = (u32_at(bytes, 20) as usize).clamp(RSDP_V2_SIZE, bytes.len());
+            if !checksum_ok(&bytes[..len]) {
+                return Err(anyhow::anyhow!("Bad extended RSDP checksum"));
+            }
+            rsdp.xsdt = PhysAddr::try_new(u64_at(bytes, 24))
+                .ok()
+                .filter(|xsdt| !xsdt.is_null());
+        }
+        Ok(rsdp)
+    }
+}
+
+/// Looks for the RSDP where BIOS machines keep it, the first KiB of the EBDA and the read-only
+/// area below 1 MiB, always on a 16 byte boundary.
+fn find_rsdp() -> Option<Rsdp> {
+    let ebda = unsafe { *phys_to_virt(PhysAddr::new(EBDA_POINTER)).as_ptr::<u16>() } as u64 * 16;
+    let regions = [
+        (ebda, EBDA_SEARCH_SIZE),
+        (BIOS_AREA_START, (BIOS_AREA_END - BIOS_AREA_START) as usize),
+    ];
+
+    regions
+        .into_iter()
+        .filter(|&(start, _)| start != 0)
+        .find_map(|(start, len)| {
+            let area =
+                unsafe { slice::from_raw_parts(phys_to_virt(PhysAddr::new(start)).as_ptr(), len) };
+            (0..=len - RSDP_V1_SIZE).step_by(16).find_map(|offset| {
+                let end = (offset + RSDP_V2_SIZE).min(len);
+                Rsdp::parse(&area[offset..end]).ok()
+            })
+        })
+}
+
+/// A system description table, the header included.
+#[derive(Debug, Clone, Copy)]
+pub struct Sdt {
+    pub bytes: &'static [u8],
+}
+
+impl Sdt {
+    /// `None` when the table doesn't fit in the direct map, as firmware may put them anywhere.
+    ///
+    /// # Safety
+    /// `phys` must point at an ACPI table.
+    unsafe fn new(phys: PhysAddr) -> Option<Self> {
+        if !is_direct_mapped(phys, SDT_HEADER_SIZE as u64) {
+            return None;
+        }
+        let addr = phys_to_virt(phys).as_ptr::<u8>();
+        let header = unsafe { slice::from_raw_parts(addr, SDT_HEADER_SIZE) };
+
+        // the length comes from the table itself, so it is checked again
+        let len = (u32_at(header, 4) as usize).max(SDT_HEADER_SIZE);
+        if !is_direct_mapped(phys, len as u64) {
+            return None;
+        }
+        Some(Self {
+            bytes: unsafe { slice::from_raw_parts(addr, len) },
+        })
+    }
+
+    pub fn signature(&self) -> &'static [u8] {
+        &self.bytes[..4]
+    }
+
+    /// Everything after the header.
+    pub fn data(&self) -> &'static [u8] {
+        &self.bytes[SDT_HEADER_SIZE..]
+    }
+
+    pub fn is_valid(&self) -> bool {
+        checksum_ok(self.bytes)
+    }
+}
+
+/// Iterates the tables listed in the XSDT, or the RSDT before ACPI 2.0, skipping any with a bad
+/// checksum or out of reach of the direct map.
+fn tables(rsdp: &Rsdp) -> impl Iterator<Item = Sdt> + use<> {
+    // the RSDT holds 32 bit pointers, the XSDT 64 bit ones
+    let (root, width) = match rsdp.xsdt {
+        Some(xsdt) => (xsdt, 8),
+        None => (rsdp.rsdt, 4),
+    };
+
+    let root = unsafe { Sdt::new(root) }.filter(Sdt::is_valid);
+    root.into_iter()
+        .flat_map(move |root| {
+            root.data().chunks_exact(width).filter_map(move |entry| {
+                let phys = if width == 8 {
+                    u64_at(entry, 0)
+                } else {
+                    u32_at(entry, 0) as u64
+                };
+                unsafe { Sdt::new(PhysAddr::try_new(phys).ok()?) }
+            })
+        })
+        .filter(Sdt::is_valid)
+}
+
+/// Every byte of an ACPI structure, its checksum field included, adds up to zero.
+fn checksum_ok(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+/// Where a register lives, in a Generic Address Structure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GenericAddress {
+    pub space: AddressSpace,
+    pub bit_width: u8,
+    pub bit_offset: u8,
+    pub address: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressSpace {
+    SystemMemory,
+    SystemIo,
+    PciConfig,
+    Other(u8),
+}
+
+impl GenericAddress {
+    fn parse(bytes: &[u8]) -> Self {
+        Self {
+            space: match bytes[0] {
+                0 => AddressSpace::SystemMemory,
+                1 => AddressSpace::SystemIo,
+                2 => AddressSpace::PciConfig,
+                other => AddressSpace::Other(other),
+            },
+            bit_width: bytes[1],
+            bit_offset: bytes[2],
+            address: u64_at(bytes, 4),
+        }
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fixes up the checksum byte at `at` so the whole of `bytes` sums to zero.
+    fn seal(bytes: &mut [u8], at: usize) {
+        bytes[at] = 0;
+        let sum = bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+        bytes[at] = sum.wrapping_neg();
+    }
+
+    fn rsdp(revision: u8) -> [u8; RSDP_V2_SIZE] {
+        let mut bytes = [0; RSDP_V2_SIZE];
+        bytes[..8].copy_from_slice(RSDP_SIGNATURE);
+        bytes[9..15].copy_from_slice(b"BASED ");
+        bytes[15] = revision;
+        bytes[16..20].copy_from_slice(&0x7fe_1000u32.to_le_bytes());
+        bytes[20..24].copy_from_slice(&(RSDP_V2_SIZE as u32).to_le_bytes());
+        bytes[24..32].copy_from_slice(&0x7fe_2000u64.to_le_bytes());
+        seal(&mut bytes[..RSDP_V1_SIZE], 8);
+        seal(&mut bytes, 32);
+        bytes
+    }
+
+    #[test_case]
+    fn rsdp_prefers_the_xsdt_from_acpi_2() {
+        let old = Rsdp::parse(&rsdp(0)).unwrap();
+        assert_eq!(old.rsdt, PhysAddr::new(0x7fe_1000));
+        assert_eq!(old.xsdt, None);
+
+        let new = Rsdp::parse(&rsdp(2)).unwrap();
+        assert_eq!(new.xsdt, Some(PhysAddr::new(0x7fe_2000)));
+    }
+
+    #[test_case]
+    fn rsdp_checksums_are_checked() {
+        let mut bytes = rsdp(2);
+        bytes[16] ^= 1;
+        assert!(Rsdp::parse(&bytes).is_err());
+
+        // only the extended part is off
+        let mut bytes = rsdp(2);
+        bytes[28] ^= 1;
+        assert!(Rsdp::parse(&bytes).is_err());
+        assert!(Rsdp::parse(&bytes[..RSDP_V1_SIZE]).is_ok());
+    }
+
+    #[test_case]
+    fn tables_past_the_direct_map_are_skipped() {
+        use crate::memory::frame::MAX_PHYSICAL_MEMORY;
+
+        // neither is ever read, the header wouldn't fit
+        assert!(unsafe { Sdt::new(PhysAddr::new(MAX_PHYSICAL_MEMORY)) }.is_none());
+        assert!(unsafe { Sdt::new(PhysAddr::new(MAX_PHYSICAL_MEMORY - 16)) }.is_none());
+    }
+}
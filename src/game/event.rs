@@ -1,38 +1,73 @@
-#[repr(usize)]
-#[derive(Clone, Copy, PartialEq, Eq)]
-pub enum Play {
-    One,
-    Two,
-    Three,
-    Four,
-    Five,
-    Six,
-    Seven,
-    Eight,
-    Nine,
-}
+use tictactoe::{event::Play, table::Rules};
+
+use crate::input::{KeyCode, KeyEvent};
 
 #[derive(Clone, Copy, PartialEq, Eq)]
-pub enum Player {
-    X,
-    O,
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
 }
-impl Player {
-    pub fn flip(&self) -> Self {
-        match self {
-            Player::X => Player::O,
-            Player::O => Player::X,
-        }
+
+impl Direction {
+    /// The cell next to `cell` on the board, staying put at the edges.
+    pub fn step(self, cell: usize, rules: Rules) -> usize {
+        let (row, col) = rules.position(cell);
+        let (row, col) = match self {
+            Direction::Up => (row.saturating_sub(1), col),
+            Direction::Down => ((row + 1).min(rules.height - 1), col),
+            Direction::Left => (row, col.saturating_sub(1)),
+            Direction::Right => (row, (col + 1).min(rules.width - 1)),
+        };
+        rules.index(row, col)
     }
 }
 
-#[derive(Clone, Copy)]
-pub struct Event {
-    pub play: Play,
-    pub player: Player,
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Play(Play),
+    Move(Direction),
+    /// Enter or Space, places on the cursor or answers yes.
+    Confirm,
+    Undo,
+    Redo,
+    Yes,
+    No,
 }
-impl Event {
-    pub fn new(play: Play, player: Player) -> Self {
-        Self { play, player }
+
+impl Input {
+    /// What a key press means to the game, `None` for keys it doesn't use.
+    pub fn from_key(event: &KeyEvent) -> Option<Self> {
+        if !event.is_press() {
+            return None;
+        }
+
+        // the numpad only steers with num lock off, otherwise it types digits like the top row
+        let input = match (event.code, event.char) {
+            (KeyCode::Z, _) if event.modifiers.ctrl => Input::Undo,
+            (KeyCode::Y, _) if event.modifiers.ctrl => Input::Redo,
+            (KeyCode::Backspace, _) | (_, Some('u' | 'U')) => Input::Undo,
+            (_, Some('r' | 'R')) => Input::Redo,
+            (KeyCode::ArrowUp, _) | (KeyCode::Numpad8, None) | (_, Some('w' | 'W')) => {
+                Input::Move(Direction::Up)
+            }
+            (KeyCode::ArrowDown, _) | (KeyCode::Numpad2, None) | (_, Some('s' | 'S')) => {
+                Input::Move(Direction::Down)
+            }
+            (KeyCode::ArrowLeft, _) | (KeyCode::Numpad4, None) | (_, Some('a' | 'A')) => {
+                Input::Move(Direction::Left)
+            }
+            (KeyCode::ArrowRight, _) | (KeyCode::Numpad6, None) | (_, Some('d' | 'D')) => {
+                Input::Move(Direction::Right)
+            }
+            (KeyCode::Return | KeyCode::NumpadEnter | KeyCode::Spacebar, _)
+            | (KeyCode::Numpad5, None) => Input::Confirm,
+            (_, Some(digit @ '1'..='9')) => Input::Play(Play(digit as usize - '1' as usize)),
+            (_, Some('y' | 'Y')) => Input::Yes,
+            (_, Some('n' | 'N')) | (KeyCode::Escape, _) => Input::No,
+            _ => return None,
+        };
+        Some(input)
     }
 }
@@ -1,40 +1,391 @@
 use core::time::Duration;
 
-use alloc::{string::ToString, vec::Vec};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
 
 pub mod event;
-pub mod table;
+pub mod score;
 
-use super::interrupts::{keyboard::EVENT_QUEUE, sleep};
+use super::input::Subscriber;
+use super::time::{Instant, get_ticks, sleep};
 use super::vga::WRITER;
-use event::{Event, Player};
-use table::Table;
+use event::Input;
+use score::{Score, Seat};
+use tictactoe::{
+    ai::{self, Difficulty, Rng},
+    event::{Event, Play, Player},
+    table::{Outcome, Rules, Table},
+};
 
-pub fn run_game() {
-    let mut table = Table::new();
-    let mut player = Player::X;
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Opponent {
+    Human,
+    Computer {
+        difficulty: Difficulty,
+        plays: Player,
+    },
+}
+
+impl Opponent {
+    /// Difficulty of the computer when it is `player`'s turn, `None` when a human moves.
+    fn moves_for(&self, player: Player) -> Option<Difficulty> {
+        match *self {
+            Opponent::Computer { difficulty, plays } if plays == player => Some(difficulty),
+            _ => None,
+        }
+    }
+
+    /// The seat the computer holds for the whole match, seats are handed out as in the first
+    /// round, where the first seat plays X.
+    fn computer_seat(&self) -> Option<Seat> {
+        match *self {
+            Opponent::Human => None,
+            Opponent::Computer {
+                plays: Player::X, ..
+            } => Some(Seat::First),
+            Opponent::Computer {
+                plays: Player::O, ..
+            } => Some(Seat::Second),
+        }
+    }
+
+    /// The opponent as it plays in a round where `x_seat` plays X.
+    fn for_round(&self, x_seat: Seat) -> Opponent {
+        match *self {
+            Opponent::Human => Opponent::Human,
+            Opponent::Computer { difficulty, .. } => Opponent::Computer {
+                difficulty,
+                plays: if self.computer_seat() == Some(x_seat) {
+                    Player::X
+                } else {
+                    Player::O
+                },
+            },
+        }
+    }
+
+    /// What a seat is called on the scoreboard.
+    fn name(&self, seat: Seat) -> &'static str {
+        match (self.computer_seat(), seat) {
+            (None, Seat::First) => "Player 1",
+            (None, Seat::Second) => "Player 2",
+            (Some(computer), seat) if computer == seat => "Computer",
+            (Some(_), _) => "You",
+        }
+    }
+}
+
+/// Board sizes on offer, all of them fit on screen.
+const BOARDS: [(&str, Rules); 5] = [
+    ("3x3, three in a row", Rules::CLASSIC),
+    ("4x4, three in a row", board(4, 4, 3)),
+    ("5x5, four in a row", board(5, 5, 4)),
+    ("7x6, four in a row", board(7, 6, 4)),
+    ("15x15, five in a row (gomoku)", board(15, 15, 5)),
+];
+
+const fn board(width: usize, height: usize, win_length: usize) -> Rules {
+    Rules {
+        width,
+        height,
+        win_length,
+    }
+}
+
+// shortest time between a human move and the computer's reply
+const COMPUTER_PAUSE: Duration = Duration::from_millis(300);
+
+/// Match lengths on offer, `None` keeps going until the players stop.
+const MATCHES: [(&str, Option<u32>); 4] = [
+    ("Single game", Some(1)),
+    ("Best of 3", Some(3)),
+    ("Best of 5", Some(5)),
+    ("Endless, until you stop", None),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    pub rules: Rules,
+    /// As in the first round of a match.
+    pub opponent: Opponent,
+    pub rounds: Option<u32>,
+}
 
-    WRITER.lock().draw_table(&table, Vec::new(), None);
+/// Asks how the next games should be played.
+pub fn menu(keys: &mut Subscriber) -> Settings {
+    let mode = choose(
+        keys,
+        "Tic Tac Toe",
+        &["Two players", "Play against the computer"],
+    );
+
+    let names = BOARDS.map(|(name, _)| name);
+    let (_, rules) = BOARDS[choose(keys, "Board", &names)];
+
+    let opponent = if mode == 0 {
+        Opponent::Human
+    } else {
+        computer(keys)
+    };
+
+    let names = MATCHES.map(|(name, _)| name);
+    let (_, rounds) = MATCHES[choose(keys, "Match", &names)];
+
+    Settings {
+        rules,
+        opponent,
+        rounds,
+    }
+}
+
+fn computer(keys: &mut Subscriber) -> Opponent {
+    let difficulty = match choose(keys, "Difficulty", &["Easy", "Medium", "Hard"]) {
+        0 => Difficulty::Easy,
+        1 => Difficulty::Medium,
+        _ => Difficulty::Hard,
+    };
+    let plays = match choose(keys, "Play as", &["X (moves first)", "O (moves second)"]) {
+        0 => Player::O,
+        _ => Player::X,
+    };
+
+    Opponent::Computer { difficulty, plays }
+}
+
+fn choose(keys: &mut Subscriber, title: &str, options: &[&str]) -> usize {
+    WRITER.lock().draw_menu(title, options);
+    keys.clear();
 
     loop {
-        if !EVENT_QUEUE.read().is_empty() {
-            let mut errors = Vec::new();
-            for play in EVENT_QUEUE.write().drain(..) {
-                let event = Event::new(play, player);
-                match table.play(event) {
-                    Ok(_) => player = player.flip(),
-                    Err(e) => errors.push(e.to_string()),
-                }
-            }
+        if let Input::Play(play) = next_input(keys)
+            && play.0 < options.len()
+        {
+            return play.0;
+        }
+    }
+}
+
+/// Plays rounds until the match is decided or the players stop, and shows the final score.
+pub fn play_match(keys: &mut Subscriber, settings: Settings) {
+    let mut score = Score::new(settings.rounds);
+
+    loop {
+        let round = score.played() + 1;
+        let x_seat = score.first_mover();
+        let header = header(settings, &score, round, x_seat);
+        let table = run_game(
+            keys,
+            &header,
+            settings.rules,
+            settings.opponent.for_round(x_seat),
+        );
+
+        // the finished board again, this time with the round counted
+        score.record(table.outcome());
+        let header = self::header(settings, &score, round, x_seat);
+        let draw_finished = |header: &str| {
+            WRITER
+                .lock()
+                .draw_table(&table, header, None, Vec::new(), table.outcome())
+        };
+        draw_finished(&header);
+
+        if score.is_decided() {
+            break;
+        }
+        WRITER.lock().draw_prompt("Next round? (Y/N)", 0x0f);
+        if !confirm(keys) {
+            draw_finished(&header);
+            break;
+        }
+    }
 
-            let winner = table.check_wins();
-            WRITER.lock().draw_table(&table, errors, winner);
+    if score.rounds() != Some(1) {
+        let (first, second) = (score.wins(Seat::First), score.wins(Seat::Second));
+        let result = match score.leader() {
+            Some(seat) => format!(
+                "{} wins the match {} - {}",
+                settings.opponent.name(seat),
+                first.max(second),
+                first.min(second)
+            ),
+            None => format!("The match is tied {} - {}", first, second),
+        };
+        WRITER.lock().draw_prompt(&result, 0x0A); // light green
+    }
+}
+
+/// Round and score line shown above the board, `x_seat` plays X in that round.
+fn header(settings: Settings, score: &Score, round: u32, x_seat: Seat) -> String {
+    let round = match score.rounds() {
+        Some(rounds) => format!("Round {} of {}", round, rounds),
+        None => format!("Round {}", round),
+    };
+    let side = |seat: Seat| {
+        let symbol = if seat == x_seat { 'X' } else { 'O' };
+        format!("{} ({})", settings.opponent.name(seat), symbol)
+    };
+
+    format!(
+        "{}   {} {} - {} {}   Draws {}",
+        round,
+        side(Seat::First),
+        score.wins(Seat::First),
+        score.wins(Seat::Second),
+        side(Seat::Second),
+        score.draws()
+    )
+}
+
+fn run_game(keys: &mut Subscriber, header: &str, rules: Rules, opponent: Opponent) -> Table {
+    let mut table = Table::with_rules(rules);
+    let mut rng = Rng::new(unsafe { core::arch::x86_64::_rdtsc() } ^ get_ticks());
+
+    // the center is the most useful first move, so that's where the cursor starts
+    let mut cursor = rules.index(rules.height / 2, rules.width / 2);
+
+    keys.clear();
+    redraw(&table, header, cursor, opponent, Vec::new());
+
+    loop {
+        if let Some(difficulty) = opponent.moves_for(table.turn()) {
+            let thinking = Instant::now();
+            let player = table.turn();
+            let play = ai::choose_move(&table, player, difficulty, &mut rng)
+                .expect("Computer has no move on an unfinished board");
+
+            // a short pause so the reply doesn't appear in the same frame as the human's move,
+            // minus however long the search already took on the bigger boards
+            sleep(COMPUTER_PAUSE.saturating_sub(thinking.elapsed()));
+            table
+                .play(Event::new(play, player))
+                .expect("Computer picked an occupied cell");
 
-            if winner.is_some() {
-                break;
+            let outcome = redraw(&table, header, cursor, opponent, Vec::new());
+            keys.clear();
+            if outcome.is_over() {
+                return table;
             }
+            continue;
+        }
+
+        // keys pressed during the computer's turn were cleared after its move
+        let errors = match next_input(keys) {
+            Input::Move(direction) => {
+                cursor = direction.step(cursor, rules);
+                Vec::new()
+            }
+            Input::Confirm => place(&mut table, cursor),
+            // digits only name cells on boards small enough to number them all
+            Input::Play(play) if rules.cells() <= 9 => {
+                cursor = play.0;
+                place(&mut table, cursor)
+            }
+            Input::Play(_) => continue,
+            Input::Undo => undo_turn(&mut table, opponent),
+            Input::Redo => redo_turn(&mut table, opponent),
+            Input::Yes | Input::No => continue,
+        };
+
+        let outcome = redraw(&table, header, cursor, opponent, errors);
+        if outcome.is_over() {
+            return table;
+        }
+    }
+}
+
+fn place(table: &mut Table, cell: usize) -> Vec<String> {
+    match table.play(Event::new(Play(cell), table.turn())) {
+        Ok(()) => Vec::new(),
+        Err(e) => vec![e.to_string()],
+    }
+}
+
+/// Takes back the last move, and the computer's reply along with it, so it is a human's turn again.
+fn undo_turn(table: &mut Table, opponent: Opponent) -> Vec<String> {
+    if table.undo().is_none() {
+        return vec!["Nothing to undo".to_string()];
+    }
+    while opponent.moves_for(table.turn()).is_some() && table.undo().is_some() {}
+    Vec::new()
+}
+
+fn redo_turn(table: &mut Table, opponent: Opponent) -> Vec<String> {
+    if table.redo().is_none() {
+        return vec!["Nothing to redo".to_string()];
+    }
+    while opponent.moves_for(table.turn()).is_some() && table.redo().is_some() {}
+    Vec::new()
+}
+
+/// Draws the board, with the cursor only while a human is about to move, and returns the outcome.
+fn redraw(
+    table: &Table,
+    header: &str,
+    cursor: usize,
+    opponent: Opponent,
+    errors: Vec<String>,
+) -> Outcome {
+    let outcome = table.outcome();
+    let human_to_move = !outcome.is_over() && opponent.moves_for(table.turn()).is_none();
+    WRITER.lock().draw_table(
+        table,
+        header,
+        human_to_move.then_some(cursor),
+        errors,
+        outcome,
+    );
+    outcome
+}
+
+/// What to do once the players are done with a set of matches.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Next {
+    Menu,
+    ShutDown,
+    Reboot,
+}
+
+pub fn what_next(keys: &mut Subscriber) -> Next {
+    match choose(
+        keys,
+        "Thanks for playing!",
+        &["Back to the menu", "Shut down", "Reboot"],
+    ) {
+        0 => Next::Menu,
+        1 => Next::ShutDown,
+        _ => Next::Reboot,
+    }
+}
+
+/// Asks whether to start another match with the same settings.
+pub fn play_again(keys: &mut Subscriber) -> bool {
+    WRITER.lock().draw_prompt("Play again? (Y/N)", 0x0f);
+    confirm(keys)
+}
+
+/// Waits for a yes/no answer to the prompt on screen.
+fn confirm(keys: &mut Subscriber) -> bool {
+    keys.clear();
+
+    loop {
+        match next_input(keys) {
+            Input::Yes | Input::Confirm => return true,
+            Input::No => return false,
+            _ => {}
         }
+    }
+}
 
-        sleep(Duration::from_millis(1));
+/// Sleeps until a key the game understands is pressed.
+fn next_input(keys: &mut Subscriber) -> Input {
+    loop {
+        if let Some(input) = Input::from_key(&keys.wait()) {
+            return input;
+        }
     }
 }
@@ -0,0 +1,148 @@
+use tictactoe::{event::Player, table::Outcome};
+
+/// One of the two sides of a match, as opposed to the symbol it plays in a given round, the
+/// sides swap symbols every round so the first move alternates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Seat {
+    First,
+    Second,
+}
+
+impl Seat {
+    pub fn other(&self) -> Seat {
+        match self {
+            Seat::First => Seat::Second,
+            Seat::Second => Seat::First,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Score {
+    wins: [u32; 2],
+    draws: u32,
+    // best of this many rounds, `None` plays on until the players stop
+    rounds: Option<u32>,
+}
+
+impl Score {
+    pub fn new(rounds: Option<u32>) -> Self {
+        Self {
+            wins: [0; 2],
+            draws: 0,
+            rounds,
+        }
+    }
+
+    pub fn rounds(&self) -> Option<u32> {
+        self.rounds
+    }
+
+    pub fn played(&self) -> u32 {
+        self.wins[0] + self.wins[1] + self.draws
+    }
+
+    pub fn wins(&self, seat: Seat) -> u32 {
+        self.wins[seat as usize]
+    }
+
+    pub fn draws(&self) -> u32 {
+        self.draws
+    }
+
+    /// The seat playing X, and so moving first, in the upcoming round.
+    pub fn first_mover(&self) -> Seat {
+        if self.played().is_multiple_of(2) {
+            Seat::First
+        } else {
+            Seat::Second
+        }
+    }
+
+    /// Seat that plays `player` in the upcoming round.
+    pub fn seat_of(&self, player: Player) -> Seat {
+        match player {
+            Player::X => self.first_mover(),
+            Player::O => self.first_mover().other(),
+        }
+    }
+
+    /// Counts a finished round, unfinished ones are ignored.
+    pub fn record(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Win(player, _) => self.wins[self.seat_of(player) as usize] += 1,
+            Outcome::Draw => self.draws += 1,
+            Outcome::InProgress => {}
+        }
+    }
+
+    /// Whether the match is over, either every round is played or the trailing seat can no longer
+    /// catch up in the rounds left.
+    pub fn is_decided(&self) -> bool {
+        let Some(rounds) = self.rounds else {
+            return false;
+        };
+        let remaining = rounds.saturating_sub(self.played());
+        let (first, second) = (self.wins(Seat::First), self.wins(Seat::Second));
+        remaining == 0 || first > second + remaining || second > first + remaining
+    }
+
+    /// The seat with more wins, `None` while tied.
+    pub fn leader(&self) -> Option<Seat> {
+        match self.wins(Seat::First).cmp(&self.wins(Seat::Second)) {
+            core::cmp::Ordering::Greater => Some(Seat::First),
+            core::cmp::Ordering::Less => Some(Seat::Second),
+            core::cmp::Ordering::Equal => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tictactoe::table::Win;
+
+    fn win(player: Player) -> Outcome {
+        Outcome::Win(
+            player,
+            Win {
+                start: (0, 0),
+                end: (0, 2),
+            },
+        )
+    }
+
+    #[test_case]
+    fn seats_swap_symbols_every_round() {
+        let mut score = Score::new(Some(5));
+        assert_eq!(score.seat_of(Player::X), Seat::First);
+
+        // X wins both times, but X is a different seat each round
+        score.record(win(Player::X));
+        assert_eq!(score.seat_of(Player::X), Seat::Second);
+        score.record(win(Player::X));
+
+        assert_eq!(score.wins(Seat::First), 1);
+        assert_eq!(score.wins(Seat::Second), 1);
+        assert_eq!(score.leader(), None);
+    }
+
+    #[test_case]
+    fn match_ends_once_the_lead_is_out_of_reach() {
+        let mut score = Score::new(Some(3));
+        score.record(win(Player::X));
+        score.record(Outcome::Draw);
+        assert!(!score.is_decided());
+
+        // first seat plays X again in the third round
+        score.record(win(Player::X));
+        assert!(score.is_decided());
+        assert_eq!(score.leader(), Some(Seat::First));
+
+        let mut endless = Score::new(None);
+        for _ in 0..10 {
+            endless.record(win(Player::O));
+        }
+        assert!(!endless.is_decided());
+    }
+}
@@ -0,0 +1,81 @@
+use lazy_static::lazy_static;
+use x86_64::{
+    VirtAddr,
+    instructions::{
+        segmentation::{CS, DS, ES, SS, Segment},
+        tables::load_tss,
+    },
+    structures::{
+        gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector},
+        tss::TaskStateSegment,
+    },
+};
+
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
+pub const MACHINE_CHECK_IST_INDEX: u16 = 1;
+pub const NMI_IST_INDEX: u16 = 2;
+
+const STACK_SIZE: usize = 4096 * 5;
+
+static mut DOUBLE_FAULT_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+static mut MACHINE_CHECK_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+static mut NMI_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+// loaded into RSP on any interrupt or syscall that comes in from ring 3
+static mut PRIVILEGE_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+
+fn stack_top(stack: *const [u8; STACK_SIZE]) -> VirtAddr {
+    (VirtAddr::from_ptr(stack) + STACK_SIZE as u64).align_down(16u64)
+}
+
+#[allow(dead_code)]
+pub struct Selectors {
+    pub kernel_code: SegmentSelector,
+    pub kernel_data: SegmentSelector,
+    pub user_data: SegmentSelector,
+    pub user_code: SegmentSelector,
+    pub tss: SegmentSelector,
+}
+
+lazy_static! {
+    static ref TSS: TaskStateSegment = {
+        let mut tss = TaskStateSegment::new();
+        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] =
+            stack_top(&raw const DOUBLE_FAULT_STACK);
+        tss.interrupt_stack_table[MACHINE_CHECK_IST_INDEX as usize] =
+            stack_top(&raw const MACHINE_CHECK_STACK);
+        tss.interrupt_stack_table[NMI_IST_INDEX as usize] = stack_top(&raw const NMI_STACK);
+        tss.privilege_stack_table[0] = stack_top(&raw const PRIVILEGE_STACK);
+        tss
+    };
+    static ref GDT: (GlobalDescriptorTable, Selectors) = {
+        let mut gdt = GlobalDescriptorTable::new();
+        // kernel code/data followed by user data/code is the layout SYSCALL/SYSRET expect
+        let selectors = Selectors {
+            kernel_code: gdt.append(Descriptor::kernel_code_segment()),
+            kernel_data: gdt.append(Descriptor::kernel_data_segment()),
+            user_data: gdt.append(Descriptor::user_data_segment()),
+            user_code: gdt.append(Descriptor::user_code_segment()),
+            tss: gdt.append(Descriptor::tss_segment(&TSS)),
+        };
+        (gdt, selectors)
+    };
+}
+
+/// Replaces the minimal GDT from `boot.asm` and reloads every segment register.
+pub fn init() {
+    let (gdt, selectors) = &*GDT;
+    gdt.load();
+
+    unsafe {
+        CS::set_reg(selectors.kernel_code);
+        SS::set_reg(selectors.kernel_data);
+        DS::set_reg(selectors.kernel_data);
+        ES::set_reg(selectors.kernel_data);
+        load_tss(selectors.tss);
+    }
+}
+
+#[allow(dead_code)]
+pub fn selectors() -> &'static Selectors {
+    &GDT.1
+}
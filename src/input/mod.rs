@@ -0,0 +1,160 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+pub use pc_keyboard::KeyCode;
+use x86_64::instructions::interrupts;
+
+use crate::sync::ring::RingBuffer;
+
+const MAX_SUBSCRIBERS: usize = 4;
+const SUBSCRIBER_QUEUE_SIZE: usize = 64;
+
+// one queue per subscriber, filled by the keyboard interrupt and drained by whoever owns the slot
+static QUEUES: [RingBuffer<KeyEvent, SUBSCRIBER_QUEUE_SIZE>; MAX_SUBSCRIBERS] =
+    [const { RingBuffer::new() }; MAX_SUBSCRIBERS];
+static ACTIVE: [AtomicBool; MAX_SUBSCRIBERS] = [const { AtomicBool::new(false) }; MAX_SUBSCRIBERS];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyState {
+    Pressed,
+    Released,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+    pub caps_lock: bool,
+    pub num_lock: bool,
+}
+
+impl From<&pc_keyboard::Modifiers> for Modifiers {
+    fn from(modifiers: &pc_keyboard::Modifiers) -> Self {
+        Self {
+            shift: modifiers.is_shifted(),
+            ctrl: modifiers.is_ctrl(),
+            alt: modifiers.is_alt() || modifiers.is_altgr(),
+            caps_lock: modifiers.capslock,
+            num_lock: modifiers.numlock,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub state: KeyState,
+    /// Modifier state after this event was applied.
+    pub modifiers: Modifiers,
+    /// The character the key produces with the current layout and modifiers, only set on presses.
+    pub char: Option<char>,
+}
+
+impl KeyEvent {
+    pub fn is_press(&self) -> bool {
+        self.state == KeyState::Pressed
+    }
+}
+
+/// A claim on one of the key event queues, every subscriber sees every key.
+pub struct Subscriber {
+    slot: usize,
+}
+
+impl Subscriber {
+    pub fn poll(&mut self) -> Option<KeyEvent> {
+        QUEUES[self.slot].pop()
+    }
+
+    /// Sleeps until the next key event arrives.
+    pub fn wait(&mut self) -> KeyEvent {
+        loop {
+            interrupts::disable();
+            if let Some(event) = self.poll() {
+                interrupts::enable();
+                return event;
+            }
+            // sti holds interrupts off for one more instruction, so an IRQ that lands after the
+            // check is taken at the hlt and wakes it instead of slipping in before it
+            interrupts::enable_and_hlt();
+        }
+    }
+
+    /// Drops every event queued so far.
+    pub fn clear(&mut self) {
+        QUEUES[self.slot].clear();
+    }
+
+    /// Number of events dropped because this subscriber fell behind.
+    #[allow(dead_code)]
+    pub fn overflows(&self) -> usize {
+        QUEUES[self.slot].overflows()
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        ACTIVE[self.slot].store(false, Ordering::Release);
+    }
+}
+
+/// Starts receiving key events, `None` when every slot is taken.
+pub fn subscribe() -> Option<Subscriber> {
+    let slot = (0..MAX_SUBSCRIBERS).find(|&slot| {
+        ACTIVE[slot]
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+    })?;
+
+    let mut subscriber = Subscriber { slot };
+    // left over from the previous owner of the slot
+    subscriber.clear();
+    Some(subscriber)
+}
+
+/// Hands `event` to every subscriber, called from the keyboard interrupt.
+pub fn publish(event: KeyEvent) {
+    for (queue, active) in QUEUES.iter().zip(&ACTIVE) {
+        if active.load(Ordering::Acquire) {
+            queue.push(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(code: KeyCode, char: Option<char>) -> KeyEvent {
+        KeyEvent {
+            code,
+            state: KeyState::Pressed,
+            modifiers: Modifiers::default(),
+            char,
+        }
+    }
+
+    #[test_case]
+    fn every_subscriber_sees_every_key() {
+        let mut first = subscribe().unwrap();
+        let mut second = subscribe().unwrap();
+
+        let event = press(KeyCode::A, Some('a'));
+        publish(event);
+        assert_eq!(first.poll(), Some(event));
+        assert_eq!(second.poll(), Some(event));
+        assert_eq!(first.poll(), None);
+    }
+
+    #[test_case]
+    fn dropped_subscribers_free_their_slot() {
+        let held: [Subscriber; MAX_SUBSCRIBERS] = core::array::from_fn(|_| subscribe().unwrap());
+        assert!(subscribe().is_none());
+        publish(press(KeyCode::Escape, None));
+
+        drop(held);
+        // whatever the previous owner left unread is not handed to the next one
+        let mut fresh = subscribe().unwrap();
+        assert_eq!(fresh.poll(), None);
+    }
+}
@@ -0,0 +1,250 @@
+use alloc::vec::Vec;
+use core::{arch::x86_64::__cpuid, ptr};
+
+use anyhow::Result;
+use spin::Once;
+use x86_64::{VirtAddr, registers::model_specific::Msr};
+
+use crate::{
+    acpi::madt::{InterruptOverride, Madt},
+    memory::paging::map_mmio,
+};
+
+use super::{InterruptIndex, PICS};
+
+pub const SPURIOUS_VECTOR: u8 = 0xff;
+
+const IA32_APIC_BASE: u32 = 0x1b;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+
+// local APIC registers, as offsets into its page
+const LAPIC_ID: usize = 0x20;
+const LAPIC_TASK_PRIORITY: usize = 0x80;
+const LAPIC_EOI: usize = 0xb0;
+const LAPIC_SPURIOUS: usize = 0xf0;
+const LAPIC_LVT_TIMER: usize = 0x320;
+const LAPIC_LVT_LINT0: usize = 0x350;
+const LAPIC_SIZE: u64 = 0x400;
+
+const LAPIC_SOFTWARE_ENABLE: u32 = 1 << 8;
+const LVT_MASKED: u32 = 1 << 16;
+
+// the I/O APIC is reached through an index and a data register
+const IOAPIC_SELECT: usize = 0x00;
+const IOAPIC_WINDOW: usize = 0x10;
+const IOAPIC_SIZE: u64 = 0x20;
+const IOAPIC_VERSION: u32 = 0x01;
+const IOAPIC_REDIRECTION_TABLE: u32 = 0x10;
+
+const REDIRECTION_ACTIVE_LOW: u64 = 1 << 13;
+const REDIRECTION_LEVEL_TRIGGERED: u64 = 1 << 15;
+const REDIRECTION_MASKED: u64 = 1 << 16;
+
+// set once interrupts are delivered through the APICs
+static LOCAL_APIC: Once<LocalApic> = Once::new();
+
+struct LocalApic {
+    base: VirtAddr,
+}
+
+impl LocalApic {
+    fn read(&self, register: usize) -> u32 {
+        unsafe { ptr::read_volatile((self.base.as_u64() as usize + register) as *const u32) }
+    }
+
+    fn write(&self, register: usize, value: u32) {
+        unsafe { ptr::write_volatile((self.base.as_u64() as usize + register) as *mut u32, value) }
+    }
+
+    fn id(&self) -> u8 {
+        (self.read(LAPIC_ID) >> 24) as u8
+    }
+
+    fn enable(&self) {
+        unsafe {
+            let mut base = Msr::new(IA32_APIC_BASE);
+            base.write(base.read() | APIC_BASE_ENABLE);
+        }
+
+        // accept every priority, and keep the firmware's virtual wire setup and the LAPIC timer
+        // from delivering anything
+        self.write(LAPIC_TASK_PRIORITY, 0);
+        self.write(LAPIC_LVT_LINT0, LVT_MASKED);
+        self.write(LAPIC_LVT_TIMER, LVT_MASKED);
+        self.write(
+            LAPIC_SPURIOUS,
+            LAPIC_SOFTWARE_ENABLE | SPURIOUS_VECTOR as u32,
+        );
+    }
+}
+
+struct IoApic {
+    base: VirtAddr,
+    gsi_base: u32,
+    entries: u32,
+}
+
+impl IoApic {
+    fn new(base: VirtAddr, gsi_base: u32) -> Self {
+        let mut io_apic = Self {
+            base,
+            gsi_base,
+            entries: 0,
+        };
+        io_apic.entries = ((io_apic.read(IOAPIC_VERSION) >> 16) & 0xff) + 1;
+        io_apic
+    }
+
+    fn read(&mut self, register: u32) -> u32 {
+        let base = self.base.as_u64() as usize;
+        unsafe {
+            ptr::write_volatile((base + IOAPIC_SELECT) as *mut u32, register);
+            ptr::read_volatile((base + IOAPIC_WINDOW) as *const u32)
+        }
+    }
+
+    fn write(&mut self, register: u32, value: u32) {
+        let base = self.base.as_u64() as usize;
+        unsafe {
+            ptr::write_volatile((base + IOAPIC_SELECT) as *mut u32, register);
+            ptr::write_volatile((base + IOAPIC_WINDOW) as *mut u32, value);
+        }
+    }
+
+    fn handles(&self, gsi: u32) -> bool {
+        (self.gsi_base..self.gsi_base + self.entries).contains(&gsi)
+    }
+
+    fn set_redirection(&mut self, gsi: u32, entry: u64) {
+        let register = IOAPIC_REDIRECTION_TABLE + (gsi - self.gsi_base) * 2;
+        // masked while half written
+        self.write(register, REDIRECTION_MASKED as u32);
+        self.write(register + 1, (entry >> 32) as u32);
+        self.write(register, entry as u32);
+    }
+}
+
+/// Redirection table entry delivering to `vector` on the local APIC `destination`, fixed
+/// delivery in physical destination mode.
+fn redirection_entry(vector: u8, destination: u8, irq: InterruptOverride) -> u64 {
+    let mut entry = vector as u64 | (destination as u64) << 56;
+    if irq.active_low {
+        entry |= REDIRECTION_ACTIVE_LOW;
+    }
+    if irq.level_triggered {
+        entry |= REDIRECTION_LEVEL_TRIGGERED;
+    }
+    entry
+}
+
+fn is_supported() -> bool {
+    // CPUID.01h:EDX bit 9
+    let features = __cpuid(1);
+    features.edx & (1 << 9) != 0
+}
+
+/// Switches interrupt delivery from the 8259s to the local and I/O APICs described by the MADT,
+/// routing the timer, keyboard, COM1 and HPET deadlines. Nothing is touched when it fails, so the
+/// PIC setup keeps working.
+pub fn init(madt: Option<&Madt>) -> Result<()> {
+    if !is_supported() {
+        return Err(anyhow::anyhow!("CPU has no local APIC"));
+    }
+    let madt = madt.ok_or_else(|| anyhow::anyhow!("No MADT in the ACPI tables"))?;
+    if madt.io_apics.is_empty() {
+        return Err(anyhow::anyhow!("MADT lists no I/O APIC"));
+    }
+
+    let local = LocalApic {
+        base: map_mmio(madt.local_apic_address, LAPIC_SIZE)?,
+    };
+    let mut io_apics = Vec::new();
+    for entry in &madt.io_apics {
+        let base = map_mmio(entry.address, IOAPIC_SIZE)?;
+        io_apics.push(IoApic::new(base, entry.gsi_base));
+    }
+
+    // work out every route before touching anything
+    let destination = local.id();
+    let routes = [
+        (0, InterruptIndex::Timer),
+        (1, InterruptIndex::Keyboard),
+        (4, InterruptIndex::Serial),
+        (8, InterruptIndex::Deadline),
+    ]
+    .map(|(irq, index)| route(&io_apics, madt, irq, index as u8, destination));
+    let routes: Vec<_> = routes.into_iter().collect::<Result<_>>()?;
+
+    // from here on the 8259s stay quiet, they are already remapped so a stray spurious IRQ
+    // can't be mistaken for an exception
+    if madt.has_legacy_pics {
+        unsafe { PICS.lock().disable() };
+    }
+
+    for io_apic in &mut io_apics {
+        for gsi in io_apic.gsi_base..io_apic.gsi_base + io_apic.entries {
+            io_apic.set_redirection(gsi, REDIRECTION_MASKED);
+        }
+    }
+    local.enable();
+    for (index, gsi, entry) in routes {
+        io_apics[index].set_redirection(gsi, entry);
+    }
+
+    LOCAL_APIC.call_once(|| local);
+    Ok(())
+}
+
+/// Which I/O APIC an ISA IRQ arrives at, and the redirection entry sending it to `vector`.
+fn route(
+    io_apics: &[IoApic],
+    madt: &Madt,
+    irq: u8,
+    vector: u8,
+    destination: u8,
+) -> Result<(usize, u32, u64)> {
+    let irq = madt.isa_irq(irq);
+    let index = io_apics
+        .iter()
+        .position(|io_apic| io_apic.handles(irq.gsi))
+        .ok_or_else(|| anyhow::anyhow!("No I/O APIC handles GSI {}", irq.gsi))?;
+    Ok((index, irq.gsi, redirection_entry(vector, destination, irq)))
+}
+
+pub fn is_enabled() -> bool {
+    LOCAL_APIC.get().is_some()
+}
+
+pub fn end_of_interrupt() {
+    if let Some(local) = LOCAL_APIC.get() {
+        local.write(LAPIC_EOI, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn redirection_entries_follow_the_override_flags() {
+        let edge = InterruptOverride {
+            irq: 0,
+            gsi: 2,
+            active_low: false,
+            level_triggered: false,
+        };
+        assert_eq!(redirection_entry(32, 0, edge), 32);
+
+        let level = InterruptOverride {
+            active_low: true,
+            level_triggered: true,
+            ..edge
+        };
+        let entry = redirection_entry(33, 1, level);
+        assert_eq!(entry & 0xff, 33);
+        assert_eq!(entry >> 56, 1);
+        assert_ne!(entry & REDIRECTION_ACTIVE_LOW, 0);
+        assert_ne!(entry & REDIRECTION_LEVEL_TRIGGERED, 0);
+        assert_eq!(entry & REDIRECTION_MASKED, 0);
+    }
+}
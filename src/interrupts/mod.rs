@@ -1,39 +1,50 @@
-use core::{
-    sync::atomic::{AtomicU64, Ordering},
-    time::Duration,
-};
+use core::fmt;
 use lazy_static::lazy_static;
 use pic8259::ChainedPics;
-use spin;
 use x86_64::structures::idt::PageFaultErrorCode;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 
-use crate::vga::println;
+use crate::{
+    acpi, gdt, memory,
+    multiboot::BootInfo,
+    power, serial,
+    sync::IrqMutex,
+    time,
+    vga::{println, try_println},
+};
 
+pub mod apic;
 pub mod keyboard;
 
 pub const PIC_1_OFFSET: u8 = 32;
 pub const PIC_2_OFFSET: u8 = PIC_1_OFFSET + 8;
 
-pub const TIMER_FREQUENCY_HZ: u64 = 1000;
-pub const MS_PER_TICK: u64 = 1000 / TIMER_FREQUENCY_HZ;
-
-pub static PICS: spin::Mutex<ChainedPics> =
-    spin::Mutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
-
-static TICK_COUNTER: AtomicU64 = AtomicU64::new(0);
+pub static PICS: IrqMutex<ChainedPics> =
+    IrqMutex::new(unsafe { ChainedPics::new(PIC_1_OFFSET, PIC_2_OFFSET) });
 
 #[derive(Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum InterruptIndex {
     Timer = PIC_1_OFFSET,
     Keyboard,
+    Serial = PIC_1_OFFSET + 4,
+    /// HPET one-shot deadlines, on the RTC's IRQ 8.
+    Deadline = PIC_2_OFFSET,
 }
 
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
 
+        unsafe {
+            idt.double_fault
+                .set_handler_fn(double_fault_handler)
+                .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+            idt.machine_check
+                .set_handler_fn(machine_check_handler)
+                .set_stack_index(gdt::MACHINE_CHECK_IST_INDEX);
+        }
+
         idt.page_fault.set_handler_fn(page_fault_handler);
         idt.general_protection_fault
             .set_handler_fn(general_protection_fault_handler);
@@ -45,7 +56,11 @@ lazy_static! {
 
         idt.divide_error.set_handler_fn(divide_error_handler);
         idt.debug.set_handler_fn(debug_handler);
-        idt.non_maskable_interrupt.set_handler_fn(nmi_handler);
+        unsafe {
+            idt.non_maskable_interrupt
+                .set_handler_fn(nmi_handler)
+                .set_stack_index(gdt::NMI_IST_INDEX);
+        }
         idt.breakpoint.set_handler_fn(breakpoint_handler);
         idt.overflow.set_handler_fn(overflow_handler);
         idt.bound_range_exceeded
@@ -59,38 +74,57 @@ lazy_static! {
 
         idt[InterruptIndex::Timer as u8].set_handler_fn(timer_interrupt_handler);
         idt[InterruptIndex::Keyboard as u8].set_handler_fn(keyboard_interrupt_handler);
+        idt[InterruptIndex::Serial as u8].set_handler_fn(serial_interrupt_handler);
+        idt[InterruptIndex::Deadline as u8].set_handler_fn(deadline_interrupt_handler);
+        idt[apic::SPURIOUS_VECTOR].set_handler_fn(spurious_interrupt_handler);
         idt
     };
 }
 
-pub fn init() {
-    configure_timer(TIMER_FREQUENCY_HZ as u16);
+/// Loads the IDT and routes device interrupts through the APICs, or through the 8259 PICs when
+/// there are none or `noapic` is on the command line. The timer is started by `time::init`.
+pub fn init(boot_info: &BootInfo) {
     IDT.load();
     unsafe { PICS.lock().initialize() };
-    x86_64::instructions::interrupts::enable();
-}
 
-pub fn get_ticks() -> u64 {
-    TICK_COUNTER.load(Ordering::Relaxed)
+    let apic = match boot_info.command_line_option("noapic") {
+        Some(_) => Err(anyhow::anyhow!("Disabled on the command line")),
+        None => apic::init(acpi::get().and_then(|acpi| acpi.madt.as_ref())),
+    };
+    if let Err(e) = apic {
+        println!("APIC unavailable, using the 8259 PIC: {}", e);
+
+        // COM1 is IRQ 4, which firmware usually leaves masked, and IRQ 8 on the second PIC
+        // needs the cascade on IRQ 2
+        unsafe {
+            let mut pics = PICS.lock();
+            let [master, slave] = pics.read_masks();
+            pics.write_masks(master & !(1 << 4 | 1 << 2), slave & !(1 << 0));
+        }
+    }
+    x86_64::instructions::interrupts::enable();
 }
 
-pub fn sleep(duration: Duration) {
-    let start_ticks = get_ticks();
-    let sleep_ticks = ((duration.as_millis() as u64) + MS_PER_TICK - 1) / MS_PER_TICK; // Round up
-    let target_ticks = start_ticks + sleep_ticks;
-
-    while get_ticks() < target_ticks {
-        core::hint::spin_loop();
+/// Acknowledges an interrupt with whichever controller delivered it.
+fn end_of_interrupt(index: InterruptIndex) {
+    if apic::is_enabled() {
+        apic::end_of_interrupt();
+    } else {
+        unsafe { PICS.lock().notify_end_of_interrupt(index as u8) };
     }
 }
 
 extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    TICK_COUNTER.fetch_add(1, Ordering::Relaxed);
+    time::tick();
+    #[cfg(test)]
+    crate::testing::check_timeout(time::get_ticks());
 
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Timer as u8);
-    }
+    end_of_interrupt(InterruptIndex::Timer);
+}
+
+// only there to wake a halted `time::sleep`
+extern "x86-interrupt" fn deadline_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    end_of_interrupt(InterruptIndex::Deadline);
 }
 
 extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStackFrame) {
@@ -98,9 +132,66 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(_stack_frame: InterruptStac
     let mut port = Port::new(0x60);
     let scancode: u8 = unsafe { port.read() };
     keyboard::handle_keyboard_interrupt(scancode);
-    unsafe {
-        PICS.lock()
-            .notify_end_of_interrupt(InterruptIndex::Keyboard as u8);
+    power::check_ctrl_alt_del();
+    end_of_interrupt(InterruptIndex::Keyboard);
+}
+
+extern "x86-interrupt" fn serial_interrupt_handler(_stack_frame: InterruptStackFrame) {
+    serial::handle_serial_interrupt();
+    end_of_interrupt(InterruptIndex::Serial);
+}
+
+// raised when an interrupt goes away before the local APIC could deliver it, and must not be
+// acknowledged
+extern "x86-interrupt" fn spurious_interrupt_handler(_stack_frame: InterruptStackFrame) {}
+
+// fatal handlers hand everything to the panic handler, which can take the writer even when the
+// faulting code was holding it
+
+extern "x86-interrupt" fn double_fault_handler(
+    stack_frame: InterruptStackFrame,
+    error_code: u64,
+) -> ! {
+    panic!(
+        "EXCEPTION: DOUBLE FAULT\n{}Error Code: {}\n{}\n{:#?}",
+        stack_overflow_note(),
+        error_code,
+        ControlRegisters,
+        stack_frame
+    );
+}
+
+extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
+    panic!(
+        "EXCEPTION: MACHINE CHECK\n{}\n{:#?}",
+        ControlRegisters, stack_frame
+    );
+}
+
+fn stack_overflow_note() -> &'static str {
+    use x86_64::registers::control::Cr2;
+
+    if Cr2::read().is_ok_and(memory::is_stack_guard) {
+        "Kernel stack overflow\n"
+    } else {
+        ""
+    }
+}
+
+struct ControlRegisters;
+
+impl fmt::Display for ControlRegisters {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use x86_64::registers::{
+            control::{Cr0, Cr2, Cr3, Cr4},
+            model_specific::Efer,
+        };
+
+        writeln!(f, "CR0: {:?}", Cr0::read())?;
+        writeln!(f, "CR2: {:?}", Cr2::read())?;
+        writeln!(f, "CR3: {:?}", Cr3::read())?;
+        writeln!(f, "CR4: {:?}", Cr4::read())?;
+        write!(f, "EFER: {:?}", Efer::read())
     }
 }
 
@@ -110,124 +201,96 @@ extern "x86-interrupt" fn page_fault_handler(
 ) {
     use x86_64::registers::control::Cr2;
 
-    println!("EXCEPTION: PAGE FAULT");
-    println!("Accessed Address: {:?}", Cr2::read());
-    println!("Error Code: {:?}", error_code);
-    println!("{:#?}", stack_frame);
-    panic!("Page fault");
+    panic!(
+        "EXCEPTION: PAGE FAULT\n{}Accessed Address: {:?}\nError Code: {:?}\n{:#?}",
+        stack_overflow_note(),
+        Cr2::read(),
+        error_code,
+        stack_frame
+    );
 }
 
 extern "x86-interrupt" fn general_protection_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
-    println!("EXCEPTION: GENERAL PROTECTION FAULT");
-    println!("Error Code: {}", error_code);
-    println!("{:#?}", stack_frame);
-    panic!("General protection fault");
+    panic!(
+        "EXCEPTION: GENERAL PROTECTION FAULT\nError Code: {}\n{:#?}",
+        error_code, stack_frame
+    );
 }
 
 extern "x86-interrupt" fn invalid_opcode_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: INVALID OPCODE");
-    println!("{:#?}", stack_frame);
-    panic!("Invalid opcode");
+    panic!("EXCEPTION: INVALID OPCODE\n{:#?}", stack_frame);
 }
 
 extern "x86-interrupt" fn segment_not_present_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
-    println!("EXCEPTION: SEGMENT NOT PRESENT");
-    println!("Error Code: {}", error_code);
-    println!("{:#?}", stack_frame);
-    panic!("Segment not present");
+    panic!(
+        "EXCEPTION: SEGMENT NOT PRESENT\nError Code: {}\n{:#?}",
+        error_code, stack_frame
+    );
 }
 
 extern "x86-interrupt" fn stack_segment_fault_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
-    println!("EXCEPTION: STACK SEGMENT FAULT");
-    println!("Error Code: {}", error_code);
-    println!("{:#?}", stack_frame);
-    panic!("Stack segment fault");
+    panic!(
+        "EXCEPTION: STACK SEGMENT FAULT\nError Code: {}\n{:#?}",
+        error_code, stack_frame
+    );
 }
 
 extern "x86-interrupt" fn divide_error_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: DIVIDE ERROR");
-    println!("{:#?}", stack_frame);
-    panic!("Divide error");
+    panic!("EXCEPTION: DIVIDE ERROR\n{:#?}", stack_frame);
 }
 
+// these return to the interrupted code, so they only print when the writer is free
+
 extern "x86-interrupt" fn debug_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: DEBUG");
-    println!("{:#?}", stack_frame);
+    try_println!("EXCEPTION: DEBUG\n{:#?}", stack_frame);
 }
 
 extern "x86-interrupt" fn nmi_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: NON-MASKABLE INTERRUPT");
-    println!("{:#?}", stack_frame);
+    try_println!("EXCEPTION: NON-MASKABLE INTERRUPT\n{:#?}", stack_frame);
 }
 
 extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: BREAKPOINT");
-    println!("{:#?}", stack_frame);
+    try_println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
 }
 
 extern "x86-interrupt" fn overflow_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: OVERFLOW");
-    println!("{:#?}", stack_frame);
-    panic!("Overflow");
+    panic!("EXCEPTION: OVERFLOW\n{:#?}", stack_frame);
 }
 
 extern "x86-interrupt" fn bound_range_exceeded_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: BOUND RANGE EXCEEDED");
-    println!("{:#?}", stack_frame);
-    panic!("Bound range exceeded");
+    panic!("EXCEPTION: BOUND RANGE EXCEEDED\n{:#?}", stack_frame);
 }
 
 extern "x86-interrupt" fn device_not_available_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: DEVICE NOT AVAILABLE");
-    println!("{:#?}", stack_frame);
-    panic!("Device not available");
+    panic!("EXCEPTION: DEVICE NOT AVAILABLE\n{:#?}", stack_frame);
 }
 
 extern "x86-interrupt" fn invalid_tss_handler(stack_frame: InterruptStackFrame, error_code: u64) {
-    println!("EXCEPTION: INVALID TSS");
-    println!("Error Code: {}", error_code);
-    println!("{:#?}", stack_frame);
-    panic!("Invalid TSS");
+    panic!(
+        "EXCEPTION: INVALID TSS\nError Code: {}\n{:#?}",
+        error_code, stack_frame
+    );
 }
 
 extern "x86-interrupt" fn virtualization_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: VIRTUALIZATION");
-    println!("{:#?}", stack_frame);
-    panic!("Virtualization exception");
+    panic!("EXCEPTION: VIRTUALIZATION\n{:#?}", stack_frame);
 }
 
 extern "x86-interrupt" fn security_exception_handler(
     stack_frame: InterruptStackFrame,
     error_code: u64,
 ) {
-    println!("EXCEPTION: SECURITY EXCEPTION");
-    println!("Error Code: {}", error_code);
-    println!("{:#?}", stack_frame);
-    panic!("Security exception");
-}
-
-pub fn configure_timer(frequency_hz: u16) {
-    use x86_64::instructions::port::Port;
-
-    let divisor = 1193180 / frequency_hz as u32;
-
-    let mut command_port = Port::new(0x43);
-    let mut data_port = Port::new(0x40);
-    unsafe {
-        // configure channel 0, access mode lobyte/hibyte, mode 3
-        command_port.write(0x36u8);
-
-        // data port for channel 0
-        data_port.write((divisor & 0xFF) as u8); // low byte
-        data_port.write(((divisor >> 8) & 0xFF) as u8); // high byte
-    }
+    panic!(
+        "EXCEPTION: SECURITY EXCEPTION\nError Code: {}\n{:#?}",
+        error_code, stack_frame
+    );
 }
@@ -1,54 +1,113 @@
 #![no_std]
 #![no_main]
 #![feature(custom_test_frameworks)]
-#![test_runner(crate::test_runner)]
+#![test_runner(crate::testing::test_runner)]
+#![reexport_test_harness_main = "test_main"]
 #![feature(abi_x86_interrupt)]
+#![feature(alloc_error_handler)]
 
 extern crate alloc;
 
-use talc::{ClaimOnOom, Span, Talc, Talck};
-
-use crate::{
-    interrupts::sleep,
-    vga::{WRITER, println},
-};
+use crate::{time::sleep, vga::println};
 use core::{panic::PanicInfo, time::Duration};
+mod acpi;
 mod game;
+mod gdt;
+mod input;
 mod interrupts;
+mod memory;
+#[allow(dead_code)] // most tags are only consumed by later subsystems
+mod multiboot;
+mod power;
+mod serial;
+mod sync;
+#[cfg(test)]
+mod testing;
+mod time;
 mod vga;
 
-// 64kb heap arena
-static mut ARENA: [u8; 65536] = [0; 65536];
+#[unsafe(no_mangle)]
+pub extern "C" fn main(multiboot_magic: u32, multiboot_info: usize) -> ! {
+    let boot_info =
+        multiboot::init(multiboot_magic, multiboot_info).expect("Invalid multiboot2 handoff");
 
-#[global_allocator]
-static ALLOCATOR: Talck<spin::Mutex<()>, ClaimOnOom> =
-    Talc::new(unsafe { ClaimOnOom::new(Span::from_array(core::ptr::addr_of!(ARENA).cast_mut())) })
-        .lock();
+    serial::init(serial::DEFAULT_BAUD);
+    serial::set_mirror(boot_info.command_line_option("serial").is_some());
+
+    memory::init(boot_info);
+
+    gdt::init();
+    match acpi::init(boot_info) {
+        Ok(acpi) => println!("ACPI: {}", acpi),
+        Err(e) => println!("ACPI unavailable: {}", e),
+    }
+    if let Err(e) = power::init() {
+        println!("ACPI reset register unavailable: {}", e);
+    }
+    time::init(boot_info);
+    match time::tsc_frequency() {
+        Some(hz) if time::tsc_is_invariant() => println!(
+            "Timer: {}, TSC at {} MHz, invariant",
+            time::source(),
+            hz / 1_000_000
+        ),
+        Some(hz) => println!("Timer: {}, TSC at {} MHz", time::source(), hz / 1_000_000),
+        None => println!("Timer: {}, no TSC", time::source()),
+    }
+    interrupts::init(boot_info);
+
+    #[cfg(test)]
+    test_main();
 
-#[unsafe(no_mangle)]
-pub extern "C" fn main() -> ! {
-    interrupts::init();
     println!("Hello from Rust kernel!");
+    if let Some(name) = boot_info.bootloader_name() {
+        println!("Booted by {}", name);
+    }
+    println!("Physical memory: {}", memory::frame::stats());
     sleep(Duration::from_millis(100));
     println!("Kernel booted successfully!");
     sleep(Duration::from_millis(100));
     println!("Booting game...");
     sleep(Duration::from_millis(500));
 
-    game::run_game();
+    power::watch_ctrl_alt_del();
 
+    let mut keys = input::subscribe().expect("No free input subscriber slot");
     loop {
-        unsafe {
-            core::arch::asm!("hlt");
+        let settings = game::menu(&mut keys);
+        loop {
+            game::play_match(&mut keys, settings);
+            if !game::play_again(&mut keys) {
+                break;
+            }
+        }
+
+        match game::what_next(&mut keys) {
+            game::Next::Menu => {}
+            game::Next::ShutDown => power::shutdown(),
+            game::Next::Reboot => power::reboot(),
         }
     }
 }
 
+#[cfg(not(test))]
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
-    let mut writer = WRITER.lock();
+fn panic(info: &PanicInfo) -> ! {
+    use core::fmt::Write;
+
+    x86_64::instructions::interrupts::disable();
+    // whoever held these got interrupted by the panic and is never going to release them
+    unsafe {
+        vga::WRITER.force_unlock();
+        serial::SERIAL1.force_unlock();
+    }
+
+    if serial::is_mirroring() {
+        serial::serial_println!("KERNEL PANIC! {}", info);
+    }
+    let mut writer = vga::WRITER.lock();
     writer.set_color(0x0c);
-    println!("KERNEL PANIC! {}", _info);
+    let _ = writeln!(writer, "KERNEL PANIC! {}", info);
     loop {
         unsafe {
             core::arch::asm!("hlt");
@@ -57,6 +116,7 @@ fn panic(_info: &PanicInfo) -> ! {
 }
 
 #[cfg(test)]
-fn test_runner(_tests: &[&dyn Fn()]) {
-    loop {}
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    testing::panic_handler(info)
 }
@@ -0,0 +1,273 @@
+use core::fmt;
+
+use spin::Mutex;
+use x86_64::{
+    PhysAddr,
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, PageSize, PhysFrame, Size2MiB, Size4KiB,
+        frame::PhysFrameRange,
+    },
+};
+
+use crate::multiboot::MemoryMap;
+
+pub const FRAME_SIZE: u64 = Size4KiB::SIZE;
+pub const HUGE_FRAME_SIZE: u64 = Size2MiB::SIZE;
+
+// physical memory above this is ignored, keeps the bitmap a fixed 128 KiB
+pub const MAX_PHYSICAL_MEMORY: u64 = 4 * 1024 * 1024 * 1024;
+
+const MAX_FRAMES: usize = (MAX_PHYSICAL_MEMORY / FRAME_SIZE) as usize;
+const FRAMES_PER_HUGE_FRAME: usize = (HUGE_FRAME_SIZE / FRAME_SIZE) as usize;
+const BITMAP_WORDS: usize = MAX_FRAMES / 64;
+const WORDS_PER_HUGE_FRAME: usize = FRAMES_PER_HUGE_FRAME / 64;
+
+pub static FRAME_ALLOCATOR: Mutex<BitmapFrameAllocator> = Mutex::new(BitmapFrameAllocator::new());
+
+#[derive(Debug, Clone, Copy)]
+pub struct FrameStats {
+    pub total_frames: usize,
+    pub used_frames: usize,
+}
+
+impl FrameStats {
+    pub fn free_frames(&self) -> usize {
+        self.total_frames - self.used_frames
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.total_frames as u64 * FRAME_SIZE
+    }
+
+    pub fn used_bytes(&self) -> u64 {
+        self.used_frames as u64 * FRAME_SIZE
+    }
+
+    pub fn free_bytes(&self) -> u64 {
+        self.free_frames() as u64 * FRAME_SIZE
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const MIB: u64 = 1024 * 1024;
+        write!(
+            f,
+            "{} MiB free, {} MiB used, {} MiB total",
+            self.free_bytes() / MIB,
+            self.used_bytes() / MIB,
+            self.total_bytes() / MIB
+        )
+    }
+}
+
+/// One bit per 4 KiB frame, set when the frame is in use (or not backed by usable RAM).
+pub struct BitmapFrameAllocator {
+    bitmap: [u64; BITMAP_WORDS],
+    total_frames: usize,
+    used_frames: usize,
+    next_word: usize,
+}
+
+impl BitmapFrameAllocator {
+    pub const fn new() -> Self {
+        Self {
+            bitmap: [!0; BITMAP_WORDS],
+            total_frames: 0,
+            used_frames: 0,
+            next_word: 0,
+        }
+    }
+
+    /// Seeds the allocator with every available area of the firmware memory map.
+    /// Anything still in use has to be carved back out with [`Self::reserve`].
+    pub fn add_memory_map(&mut self, memory_map: &MemoryMap) {
+        for area in memory_map.available() {
+            let start = area.base.div_ceil(FRAME_SIZE) as usize;
+            let end = ((area.end().min(MAX_PHYSICAL_MEMORY)) / FRAME_SIZE) as usize;
+
+            for frame in start..end {
+                if self.is_used(frame) {
+                    self.clear(frame);
+                    self.total_frames += 1;
+                }
+            }
+        }
+    }
+
+    /// Marks every frame overlapping `start..end` as used.
+    pub fn reserve(&mut self, start: PhysAddr, end: PhysAddr) {
+        let first = (start.as_u64() / FRAME_SIZE) as usize;
+        let last = (end.as_u64().div_ceil(FRAME_SIZE) as usize).min(MAX_FRAMES);
+
+        for frame in first..last {
+            if !self.is_used(frame) {
+                self.set(frame);
+                self.used_frames += 1;
+            }
+        }
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        FrameStats {
+            total_frames: self.total_frames,
+            used_frames: self.used_frames,
+        }
+    }
+
+    fn allocate_4kib(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        let word = (self.next_word..BITMAP_WORDS)
+            .chain(0..self.next_word)
+            .find(|&word| self.bitmap[word] != !0)?;
+
+        let frame = word * 64 + self.bitmap[word].trailing_ones() as usize;
+        self.set(frame);
+        self.used_frames += 1;
+        self.next_word = word;
+
+        Some(PhysFrame::containing_address(frame_address(frame)))
+    }
+
+    /// Finds `count` physically contiguous, 2 MiB aligned frames.
+    fn allocate_2mib(&mut self, count: usize) -> Option<PhysFrameRange<Size2MiB>> {
+        let words = count * WORDS_PER_HUGE_FRAME;
+        if count == 0 || words > BITMAP_WORDS {
+            return None;
+        }
+
+        let word = (0..=BITMAP_WORDS - words)
+            .step_by(WORDS_PER_HUGE_FRAME)
+            .find(|&word| {
+                self.bitmap[word..word + words]
+                    .iter()
+                    .all(|&bits| bits == 0)
+            })?;
+
+        self.bitmap[word..word + words].fill(!0);
+        self.used_frames += count * FRAMES_PER_HUGE_FRAME;
+
+        let start = PhysFrame::containing_address(frame_address(word * 64));
+        Some(PhysFrame::range(start, start + count as u64))
+    }
+
+    fn free_range(&mut self, first: usize, count: usize) {
+        for frame in first..first + count {
+            assert!(
+                self.is_used(frame),
+                "Double free of physical frame {:#x}",
+                frame_address(frame)
+            );
+            self.clear(frame);
+        }
+        self.used_frames -= count;
+        self.next_word = self.next_word.min(first / 64);
+    }
+
+    fn is_used(&self, frame: usize) -> bool {
+        self.bitmap[frame / 64] & (1 << (frame % 64)) != 0
+    }
+
+    fn set(&mut self, frame: usize) {
+        self.bitmap[frame / 64] |= 1 << (frame % 64);
+    }
+
+    fn clear(&mut self, frame: usize) {
+        self.bitmap[frame / 64] &= !(1 << (frame % 64));
+    }
+}
+
+fn frame_address(frame: usize) -> PhysAddr {
+    PhysAddr::new(frame as u64 * FRAME_SIZE)
+}
+
+fn frame_index<S: PageSize>(frame: PhysFrame<S>) -> usize {
+    (frame.start_address().as_u64() / FRAME_SIZE) as usize
+}
+
+unsafe impl FrameAllocator<Size4KiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size4KiB>> {
+        self.allocate_4kib()
+    }
+}
+
+unsafe impl FrameAllocator<Size2MiB> for BitmapFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame<Size2MiB>> {
+        self.allocate_2mib(1).map(|range| range.start)
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size4KiB>) {
+        self.free_range(frame_index(frame), 1);
+    }
+}
+
+impl FrameDeallocator<Size2MiB> for BitmapFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame<Size2MiB>) {
+        self.free_range(frame_index(frame), FRAMES_PER_HUGE_FRAME);
+    }
+}
+
+pub fn allocate_frame() -> Option<PhysFrame<Size4KiB>> {
+    FRAME_ALLOCATOR.lock().allocate_4kib()
+}
+
+pub fn allocate_huge_frame() -> Option<PhysFrame<Size2MiB>> {
+    FRAME_ALLOCATOR
+        .lock()
+        .allocate_2mib(1)
+        .map(|range| range.start)
+}
+
+pub fn allocate_huge_frames(count: usize) -> Option<PhysFrameRange<Size2MiB>> {
+    FRAME_ALLOCATOR.lock().allocate_2mib(count)
+}
+
+/// # Safety
+/// The frame must have come from this allocator and must no longer be referenced.
+pub unsafe fn free_frame(frame: PhysFrame<Size4KiB>) {
+    unsafe { FRAME_ALLOCATOR.lock().deallocate_frame(frame) }
+}
+
+/// # Safety
+/// The frame must have come from this allocator and must no longer be referenced.
+pub unsafe fn free_huge_frame(frame: PhysFrame<Size2MiB>) {
+    unsafe { FRAME_ALLOCATOR.lock().deallocate_frame(frame) }
+}
+
+/// # Safety
+/// The frames must have come from this allocator and must no longer be referenced.
+pub unsafe fn free_huge_frames(range: PhysFrameRange<Size2MiB>) {
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    for frame in range {
+        unsafe { allocator.deallocate_frame(frame) };
+    }
+}
+
+pub fn stats() -> FrameStats {
+    FRAME_ALLOCATOR.lock().stats()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn freed_frames_are_returned() {
+        let used = stats().used_frames;
+        let frame = allocate_frame().expect("Out of physical frames");
+        assert_eq!(stats().used_frames, used + 1);
+
+        unsafe { free_frame(frame) };
+        assert_eq!(stats().used_frames, used);
+    }
+
+    #[test_case]
+    fn huge_frames_are_aligned_and_contiguous() {
+        let range = allocate_huge_frames(2).expect("Out of physical frames");
+        assert!(range.start.start_address().is_aligned(HUGE_FRAME_SIZE));
+        assert_eq!(range.end - range.start, 2);
+
+        unsafe { free_huge_frames(range) };
+    }
+}
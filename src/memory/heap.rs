@@ -0,0 +1,127 @@
+use core::alloc::Layout;
+
+use talc::{OomHandler, Span, Talc, Talck};
+
+use super::{
+    frame::{self, HUGE_FRAME_SIZE},
+    phys_to_virt,
+};
+
+pub const DEFAULT_HEAP_LIMIT: usize = 64 * 1024 * 1024;
+
+// headroom for talc's tags when sizing a fresh span for a single allocation
+const CLAIM_OVERHEAD: usize = 4096;
+
+// 64kb heap arena, claimed before any frames are taken from the frame allocator
+static mut ARENA: [u8; 65536] = [0; 65536];
+
+#[global_allocator]
+static ALLOCATOR: Talck<spin::Mutex<()>, GrowOnOom> =
+    Talc::new(unsafe { GrowOnOom::new(Span::from_array(core::ptr::addr_of!(ARENA).cast_mut())) })
+        .lock();
+
+#[derive(Debug, Clone, Copy)]
+pub struct HeapStats {
+    pub claimed: usize,
+    pub limit: usize,
+}
+
+/// Claims the static arena first, then grows the heap in 2 MiB frames until `limit` is reached.
+pub struct GrowOnOom {
+    arena: Span,
+    last_span: Span,
+    claimed: usize,
+    limit: usize,
+}
+
+impl GrowOnOom {
+    /// # Safety
+    /// `arena` must be valid for reads and writes and unused by anything else.
+    pub const unsafe fn new(arena: Span) -> Self {
+        Self {
+            arena,
+            last_span: Span::empty(),
+            claimed: 0,
+            limit: DEFAULT_HEAP_LIMIT,
+        }
+    }
+}
+
+impl OomHandler for GrowOnOom {
+    fn handle_oom(talc: &mut Talc<Self>, layout: Layout) -> Result<(), ()> {
+        if !talc.oom_handler.arena.is_empty() {
+            let arena = core::mem::replace(&mut talc.oom_handler.arena, Span::empty());
+            talc.oom_handler.last_span = unsafe { talc.claim(arena)? };
+            talc.oom_handler.claimed += arena.size();
+            return Ok(());
+        }
+
+        let size = (layout.size() + layout.align() + CLAIM_OVERHEAD)
+            .next_multiple_of(HUGE_FRAME_SIZE as usize);
+        if talc.oom_handler.claimed + size > talc.oom_handler.limit {
+            return Err(());
+        }
+
+        let frames = frame::allocate_huge_frames(size / HUGE_FRAME_SIZE as usize).ok_or(())?;
+        let base = phys_to_virt(frames.start.start_address()).as_mut_ptr::<u8>();
+        let acme = base.wrapping_add(size);
+
+        // grow the previous span in place when the new frames happen to follow it
+        let last_span = talc.oom_handler.last_span;
+        talc.oom_handler.last_span = match last_span.get_base_acme() {
+            Some((last_base, last_acme)) if last_acme == base => unsafe {
+                talc.extend(last_span, Span::new(last_base, acme))
+            },
+            _ => match unsafe { talc.claim(Span::new(base, acme)) } {
+                Ok(span) => span,
+                Err(()) => {
+                    unsafe { frame::free_huge_frames(frames) };
+                    return Err(());
+                }
+            },
+        };
+        talc.oom_handler.claimed += size;
+
+        Ok(())
+    }
+}
+
+pub fn set_limit(limit: usize) {
+    ALLOCATOR.lock().oom_handler.limit = limit;
+}
+
+pub fn stats() -> HeapStats {
+    let talc = ALLOCATOR.lock();
+    HeapStats {
+        claimed: talc.oom_handler.claimed,
+        limit: talc.oom_handler.limit,
+    }
+}
+
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    let heap = stats();
+    panic!(
+        "Out of memory: cannot allocate {} bytes (align {}), heap at {} of {} KiB, {}",
+        layout.size(),
+        layout.align(),
+        heap.claimed / 1024,
+        heap.limit / 1024,
+        frame::stats()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    #[test_case]
+    fn heap_grows_past_the_arena() {
+        let size = 4 * 1024 * 1024;
+        let buffer = vec![0xabu8; size];
+        assert!(stats().claimed > size);
+        assert!(buffer.iter().all(|&byte| byte == 0xab));
+    }
+}
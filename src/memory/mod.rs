@@ -0,0 +1,179 @@
+use x86_64::{
+    PhysAddr, VirtAddr,
+    registers::{
+        control::{Cr0, Cr0Flags},
+        model_specific::{Efer, EferFlags},
+    },
+    structures::paging::{Page, PageSize, PageTableFlags, Size2MiB, Size4KiB},
+};
+
+use crate::multiboot::BootInfo;
+
+#[allow(dead_code)]
+pub mod frame;
+pub mod heap;
+#[allow(dead_code)]
+pub mod paging;
+
+unsafe extern "C" {
+    static __kernel_start: u8;
+    static __kernel_end: u8;
+    static __text_start: u8;
+    static __text_end: u8;
+    static __rodata_start: u8;
+    static __rodata_end: u8;
+    static __data_start: u8;
+    static __data_end: u8;
+    static __bss_start: u8;
+    static __bss_end: u8;
+    static stack_guard: u8;
+}
+
+// first MiB holds the IVT, BIOS data area, EBDA and VGA memory
+const LOW_MEMORY_END: u64 = 0x10_0000;
+
+// boot.asm maps the first 4 GiB of physical memory here (PML4[256])
+pub const PHYSICAL_MEMORY_OFFSET: u64 = 0xffff_8000_0000_0000;
+
+// the kernel is linked to run from the top 2 GiB, aliasing the first GiB of physical memory
+pub const KERNEL_OFFSET: u64 = 0xffff_ffff_8000_0000;
+
+pub fn phys_to_virt(addr: PhysAddr) -> VirtAddr {
+    VirtAddr::new(addr.as_u64() + PHYSICAL_MEMORY_OFFSET)
+}
+
+/// Whether all `len` bytes from `addr` are reachable through [`phys_to_virt`].
+pub fn is_direct_mapped(addr: PhysAddr, len: u64) -> bool {
+    addr.as_u64()
+        .checked_add(len)
+        .is_some_and(|end| end <= frame::MAX_PHYSICAL_MEMORY)
+}
+
+/// Physical range occupied by the kernel image, including the boot trampoline, page tables and
+/// stack.
+pub fn kernel_range() -> (PhysAddr, PhysAddr) {
+    (
+        PhysAddr::new(&raw const __kernel_start as u64 - KERNEL_OFFSET),
+        PhysAddr::new(&raw const __kernel_end as u64 - KERNEL_OFFSET),
+    )
+}
+
+pub fn init(boot_info: &BootInfo) {
+    let memory_map = boot_info
+        .memory_map()
+        .expect("Bootloader did not provide a memory map");
+
+    if let Some(limit) = boot_info
+        .command_line_option("heap_limit")
+        .and_then(|mib| mib.parse::<usize>().ok())
+    {
+        heap::set_limit(limit * 1024 * 1024);
+    }
+
+    let mut allocator = frame::FRAME_ALLOCATOR.lock();
+    allocator.add_memory_map(&memory_map);
+
+    allocator.reserve(PhysAddr::new(0), PhysAddr::new(LOW_MEMORY_END));
+
+    let (kernel_start, kernel_end) = kernel_range();
+    allocator.reserve(kernel_start, kernel_end);
+
+    allocator.reserve(
+        PhysAddr::new(boot_info.start_address() as u64),
+        PhysAddr::new(boot_info.end_address() as u64),
+    );
+
+    for module in boot_info.modules() {
+        allocator.reserve(PhysAddr::new(module.start), PhysAddr::new(module.end));
+    }
+    drop(allocator);
+
+    paging::init();
+    protect_kernel();
+    guard_boot_stack();
+}
+
+/// Whether `addr` lies in the unmapped page below the boot stack.
+pub fn is_stack_guard(addr: VirtAddr) -> bool {
+    let guard = VirtAddr::new(&raw const stack_guard as u64);
+    (guard..guard + Size4KiB::SIZE).contains(&addr)
+}
+
+fn guard_boot_stack() {
+    let guard = Page::<Size4KiB>::containing_address(VirtAddr::new(&raw const stack_guard as u64));
+    paging::unmap(guard).expect("Failed to unmap boot stack guard page");
+}
+
+fn section(start: &u8, end: &u8) -> (VirtAddr, VirtAddr) {
+    (
+        VirtAddr::new(start as *const u8 as u64),
+        VirtAddr::new(end as *const u8 as u64),
+    )
+}
+
+fn pages(start: VirtAddr, end: VirtAddr) -> impl Iterator<Item = Page<Size4KiB>> {
+    Page::range(
+        Page::containing_address(start),
+        Page::containing_address(end - 1u64) + 1,
+    )
+}
+
+/// Replaces the boot mapping of the kernel window (1 GiB, writable and executable) with one that
+/// only covers the kernel image: `.text` read-only, `.rodata` read-only + NX, `.data`/`.bss` NX.
+/// Read-only applies to the kernel itself too, through CR0.WP.
+fn protect_kernel() {
+    unsafe { Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE)) };
+
+    let (text_start, text_end) = unsafe { section(&__text_start, &__text_end) };
+    let (rodata_start, rodata_end) = unsafe { section(&__rodata_start, &__rodata_end) };
+    let (data_start, data_end) = unsafe { section(&__data_start, &__data_end) };
+    let (bss_start, bss_end) = unsafe { section(&__bss_start, &__bss_end) };
+    let window_end = VirtAddr::new(KERNEL_OFFSET + 1024 * 1024 * 1024);
+
+    let image_end = Page::<Size2MiB>::containing_address(bss_end - 1u64) + 1;
+    for page in Page::range(
+        Page::containing_address(VirtAddr::new(KERNEL_OFFSET)),
+        image_end,
+    ) {
+        paging::split_huge_page(page).expect("Failed to split kernel mapping");
+    }
+
+    let text = PageTableFlags::PRESENT;
+    let rodata = PageTableFlags::PRESENT | PageTableFlags::NO_EXECUTE;
+    let data = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    for (start, end, flags) in [
+        (text_start, text_end, text),
+        (rodata_start, rodata_end, rodata),
+        (data_start, data_end, data),
+        (bss_start, bss_end, data),
+    ] {
+        for page in pages(start, end) {
+            unsafe { paging::protect(page, flags) }.expect("Failed to protect kernel section");
+        }
+    }
+
+    // low memory and the boot trampoline are only reachable through the direct map from now on
+    for page in pages(VirtAddr::new(KERNEL_OFFSET), text_start) {
+        paging::unmap(page).expect("Failed to unmap kernel window");
+    }
+    for page in pages(bss_end, image_end.start_address()) {
+        paging::unmap(page).expect("Failed to unmap kernel window");
+    }
+    for page in Page::<Size2MiB>::range(image_end, Page::containing_address(window_end)) {
+        paging::unmap(page).expect("Failed to unmap kernel window");
+    }
+
+    // nothing runs from the direct map either
+    let direct_map = VirtAddr::new(PHYSICAL_MEMORY_OFFSET);
+    let direct_map_end = direct_map + frame::MAX_PHYSICAL_MEMORY;
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    for page in Page::<Size2MiB>::range(
+        Page::containing_address(direct_map),
+        Page::containing_address(direct_map_end),
+    ) {
+        unsafe { paging::protect(page, flags) }.expect("Failed to protect direct map");
+    }
+
+    // without WP ring 0 writes straight through read-only pages
+    unsafe { Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT)) };
+}
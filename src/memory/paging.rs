@@ -0,0 +1,358 @@
+use core::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use anyhow::Result;
+use spin::{Mutex, MutexGuard, Once};
+use x86_64::{
+    PhysAddr, VirtAddr,
+    instructions::tlb,
+    registers::control::Cr3,
+    structures::paging::{
+        FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageSize, PageTable,
+        PageTableFlags, PhysFrame, Size2MiB, Size4KiB, Translate, mapper::TranslateResult,
+    },
+};
+
+use super::{
+    PHYSICAL_MEMORY_OFFSET,
+    frame::{BitmapFrameAllocator, FRAME_ALLOCATOR},
+    phys_to_virt,
+};
+
+// PML4 slot 384, well away from the kernel and any future user mappings
+const MMIO_BASE: u64 = 0xffff_c000_0000_0000;
+const MMIO_SIZE: u64 = 512 * 1024 * 1024 * 1024;
+
+static KERNEL_PAGE_TABLE: Once<Mutex<OffsetPageTable<'static>>> = Once::new();
+static NEXT_MMIO: AtomicU64 = AtomicU64::new(MMIO_BASE);
+
+/// Takes over the PML4 that `boot.asm` left in CR3 and fills in every kernel entry, so address
+/// spaces that copy them share all kernel mappings made later on.
+pub fn init() {
+    let (frame, _) = Cr3::read();
+    let pml4 = unsafe { table_mut(frame) };
+
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    for (index, entry) in pml4.iter_mut().enumerate() {
+        if is_kernel_entry(index) && entry.is_unused() {
+            let table: PhysFrame = allocator
+                .allocate_frame()
+                .expect("Out of physical frames for kernel page tables");
+            unsafe { table_mut(table) }.zero();
+            entry.set_frame(table, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+        }
+    }
+    drop(allocator);
+
+    KERNEL_PAGE_TABLE.call_once(|| Mutex::new(unsafe { offset_page_table(frame) }));
+}
+
+unsafe fn table_mut(frame: PhysFrame) -> &'static mut PageTable {
+    unsafe { &mut *phys_to_virt(frame.start_address()).as_mut_ptr::<PageTable>() }
+}
+
+unsafe fn offset_page_table(pml4: PhysFrame) -> OffsetPageTable<'static> {
+    unsafe { OffsetPageTable::new(table_mut(pml4), VirtAddr::new(PHYSICAL_MEMORY_OFFSET)) }
+}
+
+pub fn kernel_page_table() -> MutexGuard<'static, OffsetPageTable<'static>> {
+    KERNEL_PAGE_TABLE
+        .get()
+        .expect("paging::init was not called")
+        .lock()
+}
+
+// the upper half holds the direct map, MMIO window and kernel image
+fn is_kernel_entry(index: usize) -> bool {
+    index >= 256
+}
+
+/// # Safety
+/// Mapping a frame that is already in use elsewhere creates aliasing mutable memory.
+pub unsafe fn map<S: PageSize + fmt::Debug>(
+    page: Page<S>,
+    frame: PhysFrame<S>,
+    flags: PageTableFlags,
+) -> Result<()>
+where
+    for<'a> OffsetPageTable<'a>: Mapper<S>,
+{
+    let mut page_table = kernel_page_table();
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    unsafe {
+        page_table.map_to(
+            page,
+            frame,
+            flags | PageTableFlags::PRESENT,
+            &mut *allocator,
+        )
+    }
+    .map_err(|e| anyhow::anyhow!("Failed to map {:?}: {:?}", page, e))?
+    .flush();
+    Ok(())
+}
+
+/// Maps `page` to a freshly allocated frame.
+pub fn map_new<S: PageSize + fmt::Debug>(
+    page: Page<S>,
+    flags: PageTableFlags,
+) -> Result<PhysFrame<S>>
+where
+    for<'a> OffsetPageTable<'a>: Mapper<S>,
+    BitmapFrameAllocator: FrameAllocator<S>,
+{
+    let frame = FRAME_ALLOCATOR
+        .lock()
+        .allocate_frame()
+        .ok_or_else(|| anyhow::anyhow!("Out of physical frames"))?;
+    unsafe { map(page, frame, flags) }?;
+    Ok(frame)
+}
+
+pub fn unmap<S: PageSize>(page: Page<S>) -> Result<PhysFrame<S>>
+where
+    for<'a> OffsetPageTable<'a>: Mapper<S>,
+{
+    let (frame, flush) = kernel_page_table()
+        .unmap(page)
+        .map_err(|e| anyhow::anyhow!("Failed to unmap {:?}: {:?}", page, e))?;
+    flush.flush();
+    Ok(frame)
+}
+
+/// # Safety
+/// Dropping permissions on memory that is still borrowed as writable or executable faults.
+pub unsafe fn protect<S: PageSize>(page: Page<S>, flags: PageTableFlags) -> Result<()>
+where
+    for<'a> OffsetPageTable<'a>: Mapper<S>,
+{
+    unsafe { kernel_page_table().update_flags(page, flags | PageTableFlags::PRESENT) }
+        .map_err(|e| anyhow::anyhow!("Failed to protect {:?}: {:?}", page, e))?
+        .flush();
+    Ok(())
+}
+
+/// Replaces a 2 MiB mapping with a page table of equivalent 4 KiB entries so parts of it can be
+/// remapped on their own. The switch is a single entry write, so the range may be in use meanwhile.
+pub fn split_huge_page(page: Page<Size2MiB>) -> Result<()> {
+    let mut page_table = kernel_page_table();
+
+    let walk_err = |e| anyhow::anyhow!("Failed to split {:?}: {:?}", page, e);
+    let p4 = page_table.level_4_table_mut();
+    let p3 = unsafe { table_mut(p4[page.p4_index()].frame().map_err(walk_err)?) };
+    let p2 = unsafe { table_mut(p3[page.p3_index()].frame().map_err(walk_err)?) };
+    let entry = &mut p2[page.p2_index()];
+
+    let flags = entry.flags();
+    if !flags.contains(PageTableFlags::PRESENT) {
+        return Err(anyhow::anyhow!("Failed to split {:?}: not mapped", page));
+    }
+    if !flags.contains(PageTableFlags::HUGE_PAGE) {
+        return Ok(());
+    }
+
+    let table_frame: PhysFrame = FRAME_ALLOCATOR
+        .lock()
+        .allocate_frame()
+        .ok_or_else(|| anyhow::anyhow!("Out of physical frames"))?;
+    let table = unsafe { table_mut(table_frame) };
+    let base = entry.addr();
+    for (index, pte) in table.iter_mut().enumerate() {
+        pte.set_addr(
+            base + index as u64 * Size4KiB::SIZE,
+            flags - PageTableFlags::HUGE_PAGE,
+        );
+    }
+
+    entry.set_addr(
+        table_frame.start_address(),
+        PageTableFlags::PRESENT | PageTableFlags::WRITABLE,
+    );
+    tlb::flush_all();
+
+    Ok(())
+}
+
+pub fn translate(addr: VirtAddr) -> Option<PhysAddr> {
+    kernel_page_table().translate_addr(addr)
+}
+
+pub fn translate_flags(addr: VirtAddr) -> Option<PageTableFlags> {
+    match kernel_page_table().translate(addr) {
+        TranslateResult::Mapped { flags, .. } => Some(flags),
+        _ => None,
+    }
+}
+
+/// Maps `size` bytes of device memory starting at `phys` as uncached and non-executable, and
+/// returns its virtual address.
+pub fn map_mmio(phys: PhysAddr, size: u64) -> Result<VirtAddr> {
+    let start = phys.align_down(Size4KiB::SIZE);
+    let end = phys
+        .as_u64()
+        .checked_add(size)
+        .and_then(|end| end.checked_next_multiple_of(Size4KiB::SIZE))
+        .and_then(|end| PhysAddr::try_new(end).ok())
+        .ok_or_else(|| anyhow::anyhow!("MMIO range at {:?} is too large", phys))?;
+    let len = end - start;
+
+    // only reserved once it fits, so a failed call leaves the window as it was
+    let base = NEXT_MMIO
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |base| {
+            base.checked_add(len)
+                .filter(|&next| next <= MMIO_BASE + MMIO_SIZE)
+        })
+        .map_err(|_| anyhow::anyhow!("MMIO window exhausted"))?;
+
+    let flags = PageTableFlags::WRITABLE
+        | PageTableFlags::NO_CACHE
+        | PageTableFlags::WRITE_THROUGH
+        | PageTableFlags::NO_EXECUTE;
+    for offset in (0..len).step_by(Size4KiB::SIZE as usize) {
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(base + offset));
+        let frame = PhysFrame::containing_address(start + offset);
+        unsafe { map(page, frame, flags) }?;
+    }
+
+    Ok(VirtAddr::new(base + (phys - start)))
+}
+
+/// A separate set of page tables sharing the kernel's half of the address space.
+pub struct AddressSpace {
+    pml4: PhysFrame,
+}
+
+impl AddressSpace {
+    pub fn new() -> Result<Self> {
+        let pml4 = FRAME_ALLOCATOR
+            .lock()
+            .allocate_frame()
+            .ok_or_else(|| anyhow::anyhow!("Out of physical frames"))?;
+
+        let table = unsafe { table_mut(pml4) };
+        table.zero();
+
+        let kernel = kernel_page_table();
+        for (index, entry) in kernel.level_4_table().iter().enumerate() {
+            if is_kernel_entry(index) {
+                table[index] = entry.clone();
+            }
+        }
+
+        Ok(Self { pml4 })
+    }
+
+    pub fn pml4_frame(&self) -> PhysFrame {
+        self.pml4
+    }
+
+    /// Page table view for editing this address space while another one is active.
+    pub fn mapper(&mut self) -> OffsetPageTable<'_> {
+        unsafe { offset_page_table(self.pml4) }
+    }
+
+    /// # Safety
+    /// The kernel half must still be mapped and the address space must outlive its use in CR3.
+    pub unsafe fn activate(&self) {
+        let (_, flags) = Cr3::read();
+        unsafe { Cr3::write(self.pml4, flags) };
+    }
+}
+
+impl Drop for AddressSpace {
+    fn drop(&mut self) {
+        let (active, _) = Cr3::read();
+        assert!(active != self.pml4, "Dropping the active address space");
+
+        // frees the page tables of the private half, mapped frames belong to whoever mapped them
+        let mut allocator = FRAME_ALLOCATOR.lock();
+        let table = unsafe { table_mut(self.pml4) };
+        for (index, entry) in table.iter().enumerate() {
+            if !is_kernel_entry(index) && entry.flags().contains(PageTableFlags::PRESENT) {
+                unsafe { free_table(entry.frame().unwrap(), 3, &mut allocator) };
+            }
+        }
+        unsafe { allocator.deallocate_frame(self.pml4) };
+    }
+}
+
+unsafe fn free_table(frame: PhysFrame, level: u8, allocator: &mut BitmapFrameAllocator) {
+    if level > 1 {
+        for entry in unsafe { table_mut(frame) }.iter() {
+            let flags = entry.flags();
+            if flags.contains(PageTableFlags::PRESENT) && !flags.contains(PageTableFlags::HUGE_PAGE)
+            {
+                unsafe { free_table(entry.frame().unwrap(), level - 1, allocator) };
+            }
+        }
+    }
+    unsafe { allocator.deallocate_frame(frame) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::frame;
+
+    #[test_case]
+    fn map_translate_unmap() {
+        // lower half, nothing lives there once boot.asm drops the identity map
+        let page = Page::<Size4KiB>::containing_address(VirtAddr::new(0x4000_0000));
+        let frame = map_new(page, PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE).unwrap();
+        assert_eq!(translate(page.start_address()), Some(frame.start_address()));
+
+        let ptr = page.start_address().as_mut_ptr::<u64>();
+        unsafe { ptr.write_volatile(0xdead_beef) };
+        let direct = phys_to_virt(frame.start_address()).as_ptr::<u64>();
+        assert_eq!(unsafe { direct.read_volatile() }, 0xdead_beef);
+
+        assert_eq!(unmap(page).unwrap(), frame);
+        assert_eq!(translate(page.start_address()), None);
+        unsafe { frame::free_frame(frame) };
+    }
+
+    #[test_case]
+    fn address_spaces_share_every_kernel_entry() {
+        let space = AddressSpace::new().unwrap();
+        let table = unsafe { table_mut(space.pml4_frame()) };
+        let kernel = kernel_page_table();
+        for (index, entry) in kernel.level_4_table().iter().enumerate() {
+            if is_kernel_entry(index) {
+                assert!(entry.flags().contains(PageTableFlags::PRESENT));
+                assert_eq!(table[index].addr(), entry.addr());
+            }
+        }
+    }
+
+    #[test_case]
+    fn mmio_is_uncached_and_not_executable() {
+        // the VGA text buffer, already in use so mapping it again is harmless
+        let addr = map_mmio(PhysAddr::new(0xb8000), 4000).unwrap();
+        assert_eq!(translate(addr), Some(PhysAddr::new(0xb8000)));
+
+        let flags = translate_flags(addr).unwrap();
+        assert!(flags.contains(PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE));
+        assert!(flags.contains(PageTableFlags::NO_EXECUTE));
+    }
+
+    #[test_case]
+    fn failed_mmio_mappings_leave_the_window_alone() {
+        let next = NEXT_MMIO.load(Ordering::Relaxed);
+        assert!(map_mmio(PhysAddr::new(0x1000), u64::MAX).is_err());
+        assert!(map_mmio(PhysAddr::new(0), MMIO_SIZE + 1).is_err());
+        assert_eq!(NEXT_MMIO.load(Ordering::Relaxed), next);
+    }
+
+    #[test_case]
+    fn kernel_text_is_read_only() {
+        use x86_64::registers::control::{Cr0, Cr0Flags};
+
+        let flags = translate_flags(VirtAddr::new(crate::main as *const () as u64)).unwrap();
+        assert!(!flags.contains(PageTableFlags::WRITABLE));
+        assert!(!flags.contains(PageTableFlags::NO_EXECUTE));
+        // the flag alone doesn't stop the kernel from writing
+        assert!(Cr0::read().contains(Cr0Flags::WRITE_PROTECT));
+    }
+}
@@ -0,0 +1,429 @@
+use core::{ffi::CStr, fmt, ptr, slice};
+
+use anyhow::Result;
+use spin::Once;
+use x86_64::PhysAddr;
+
+use crate::memory::{PHYSICAL_MEMORY_OFFSET, phys_to_virt};
+
+/// Value GRUB leaves in EAX when it hands control to a Multiboot2 kernel.
+pub const BOOTLOADER_MAGIC: u32 = 0x36d7_6289;
+
+const TAG_END: u32 = 0;
+const TAG_COMMAND_LINE: u32 = 1;
+const TAG_BOOTLOADER_NAME: u32 = 2;
+const TAG_MODULE: u32 = 3;
+const TAG_BASIC_MEMINFO: u32 = 4;
+const TAG_MEMORY_MAP: u32 = 6;
+const TAG_FRAMEBUFFER: u32 = 8;
+const TAG_ELF_SECTIONS: u32 = 9;
+const TAG_ACPI_OLD: u32 = 14;
+const TAG_ACPI_NEW: u32 = 15;
+
+static BOOT_INFO: Once<BootInfo> = Once::new();
+
+/// Validates the Multiboot2 handoff and stores the boot information for the rest of the kernel.
+pub fn init(magic: u32, phys: usize) -> Result<&'static BootInfo> {
+    let info = unsafe { BootInfo::new(magic, phys)? };
+    Ok(BOOT_INFO.call_once(|| info))
+}
+
+/// Returns the boot information parsed by [`init`].
+pub fn boot_info() -> &'static BootInfo {
+    BOOT_INFO.get().expect("multiboot::init was not called")
+}
+
+unsafe fn read<T: Copy>(addr: usize) -> T {
+    unsafe { ptr::read_unaligned(addr as *const T) }
+}
+
+unsafe fn bytes(addr: usize, len: usize) -> &'static [u8] {
+    unsafe { slice::from_raw_parts(addr as *const u8, len) }
+}
+
+fn c_str(bytes: &'static [u8]) -> &'static str {
+    CStr::from_bytes_until_nul(bytes)
+        .ok()
+        .and_then(|s| s.to_str().ok())
+        .unwrap_or("")
+}
+
+pub struct BootInfo {
+    phys: usize,
+    addr: usize,
+    size: usize,
+}
+
+impl BootInfo {
+    /// # Safety
+    /// `phys` must be the physical address of a Multiboot2 information structure that
+    /// stays untouched for the lifetime of the kernel.
+    pub unsafe fn new(magic: u32, phys: usize) -> Result<Self> {
+        if magic != BOOTLOADER_MAGIC {
+            return Err(anyhow::anyhow!(
+                "Invalid multiboot2 magic {:#x} (expected {:#x})",
+                magic,
+                BOOTLOADER_MAGIC
+            ));
+        }
+        if phys == 0 || !phys.is_multiple_of(8) {
+            return Err(anyhow::anyhow!("Misaligned multiboot2 info at {:#x}", phys));
+        }
+
+        let addr = phys_to_virt(PhysAddr::new(phys as u64)).as_u64() as usize;
+        let size = unsafe { read::<u32>(addr) } as usize;
+        if size < 16 {
+            return Err(anyhow::anyhow!(
+                "Multiboot2 info is too small ({} bytes)",
+                size
+            ));
+        }
+
+        Ok(Self { phys, addr, size })
+    }
+
+    /// Physical address range occupied by the information structure itself.
+    pub fn start_address(&self) -> usize {
+        self.phys
+    }
+
+    pub fn end_address(&self) -> usize {
+        self.phys + self.size
+    }
+
+    pub fn tags(&self) -> TagIter {
+        TagIter {
+            current: self.addr + 8,
+            end: self.addr + self.size,
+        }
+    }
+
+    pub fn command_line(&self) -> Option<&'static str> {
+        self.tags().find_map(|tag| match tag {
+            Tag::CommandLine(cmdline) => Some(cmdline),
+            _ => None,
+        })
+    }
+
+    pub fn bootloader_name(&self) -> Option<&'static str> {
+        self.tags().find_map(|tag| match tag {
+            Tag::BootloaderName(name) => Some(name),
+            _ => None,
+        })
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = Module> {
+        self.tags().filter_map(|tag| match tag {
+            Tag::Module(module) => Some(module),
+            _ => None,
+        })
+    }
+
+    pub fn memory_map(&self) -> Option<MemoryMap> {
+        self.tags().find_map(|tag| match tag {
+            Tag::MemoryMap(map) => Some(map),
+            _ => None,
+        })
+    }
+
+    pub fn framebuffer(&self) -> Option<Framebuffer> {
+        self.tags().find_map(|tag| match tag {
+            Tag::Framebuffer(framebuffer) => Some(framebuffer),
+            _ => None,
+        })
+    }
+
+    pub fn elf_sections(&self) -> Option<ElfSections> {
+        self.tags().find_map(|tag| match tag {
+            Tag::ElfSections(sections) => Some(sections),
+            _ => None,
+        })
+    }
+
+    /// Raw copy of the ACPI RSDP, preferring the ACPI 2.0+ version when both are present.
+    pub fn rsdp(&self) -> Option<&'static [u8]> {
+        let mut old = None;
+        for tag in self.tags() {
+            match tag {
+                Tag::AcpiNewRsdp(rsdp) => return Some(rsdp),
+                Tag::AcpiOldRsdp(rsdp) => old = Some(rsdp),
+                _ => {}
+            }
+        }
+        old
+    }
+
+    /// Looks up `key` in a whitespace separated `key=value` kernel command line.
+    /// Bare flags yield an empty value.
+    pub fn command_line_option(&self, key: &str) -> Option<&'static str> {
+        self.command_line()?
+            .split_ascii_whitespace()
+            .find_map(|option| match option.split_once('=') {
+                Some((k, v)) if k == key => Some(v),
+                None if option == key => Some(""),
+                _ => None,
+            })
+    }
+}
+
+impl fmt::Debug for BootInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BootInfo")
+            .field("phys", &format_args!("{:#x}", self.phys))
+            .field("size", &self.size)
+            .field("command_line", &self.command_line())
+            .field("bootloader_name", &self.bootloader_name())
+            .finish()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Tag {
+    CommandLine(&'static str),
+    BootloaderName(&'static str),
+    Module(Module),
+    BasicMemInfo { lower_kib: u32, upper_kib: u32 },
+    MemoryMap(MemoryMap),
+    Framebuffer(Framebuffer),
+    ElfSections(ElfSections),
+    AcpiOldRsdp(&'static [u8]),
+    AcpiNewRsdp(&'static [u8]),
+    Unknown { kind: u32, size: u32 },
+}
+
+pub struct TagIter {
+    current: usize,
+    end: usize,
+}
+
+impl Iterator for TagIter {
+    type Item = Tag;
+
+    fn next(&mut self) -> Option<Tag> {
+        if self.current + 8 > self.end {
+            return None;
+        }
+
+        let addr = self.current;
+        let kind = unsafe { read::<u32>(addr) };
+        let size = unsafe { read::<u32>(addr + 4) };
+        if kind == TAG_END || size < 8 || addr + size as usize > self.end {
+            self.current = self.end;
+            return None;
+        }
+
+        // tags are padded so the next one starts on an 8 byte boundary
+        self.current = (addr + size as usize + 7) & !7;
+
+        let payload = addr + 8;
+        let payload_len = size as usize - 8;
+        let tag = unsafe {
+            match kind {
+                TAG_COMMAND_LINE => Tag::CommandLine(c_str(bytes(payload, payload_len))),
+                TAG_BOOTLOADER_NAME => Tag::BootloaderName(c_str(bytes(payload, payload_len))),
+                TAG_MODULE => Tag::Module(Module {
+                    start: read::<u32>(payload) as u64,
+                    end: read::<u32>(payload + 4) as u64,
+                    command_line: c_str(bytes(payload + 8, payload_len.saturating_sub(8))),
+                }),
+                TAG_BASIC_MEMINFO => Tag::BasicMemInfo {
+                    lower_kib: read(payload),
+                    upper_kib: read(payload + 4),
+                },
+                TAG_MEMORY_MAP => Tag::MemoryMap(MemoryMap {
+                    entries: payload + 8,
+                    entry_size: read::<u32>(payload) as usize,
+                    len: payload_len.saturating_sub(8),
+                }),
+                TAG_FRAMEBUFFER => Tag::Framebuffer(Framebuffer {
+                    address: read(payload),
+                    pitch: read(payload + 8),
+                    width: read(payload + 12),
+                    height: read(payload + 16),
+                    bpp: read(payload + 20),
+                    kind: match read::<u8>(payload + 21) {
+                        0 => FramebufferKind::Indexed,
+                        1 => FramebufferKind::Rgb,
+                        _ => FramebufferKind::EgaText,
+                    },
+                }),
+                TAG_ELF_SECTIONS => Tag::ElfSections(ElfSections {
+                    count: read(payload),
+                    entry_size: read(payload + 4),
+                    string_table_index: read(payload + 8),
+                    headers: payload + 12,
+                }),
+                TAG_ACPI_OLD => Tag::AcpiOldRsdp(bytes(payload, payload_len)),
+                TAG_ACPI_NEW => Tag::AcpiNewRsdp(bytes(payload, payload_len)),
+                _ => Tag::Unknown { kind, size },
+            }
+        };
+
+        Some(tag)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Module {
+    pub start: u64,
+    pub end: u64,
+    pub command_line: &'static str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryAreaKind {
+    Available,
+    AcpiReclaimable,
+    AcpiNvs,
+    Defective,
+    Reserved,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryArea {
+    pub base: u64,
+    pub length: u64,
+    pub kind: MemoryAreaKind,
+}
+
+impl MemoryArea {
+    pub fn end(&self) -> u64 {
+        self.base + self.length
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MemoryMap {
+    entries: usize,
+    entry_size: usize,
+    len: usize,
+}
+
+impl MemoryMap {
+    pub fn areas(&self) -> impl Iterator<Item = MemoryArea> {
+        let (entries, entry_size) = (self.entries, self.entry_size);
+        let count = self.len.checked_div(entry_size).unwrap_or(0);
+
+        (0..count).map(move |i| {
+            let entry = entries + i * entry_size;
+            unsafe {
+                MemoryArea {
+                    base: read(entry),
+                    length: read(entry + 8),
+                    kind: match read::<u32>(entry + 16) {
+                        1 => MemoryAreaKind::Available,
+                        3 => MemoryAreaKind::AcpiReclaimable,
+                        4 => MemoryAreaKind::AcpiNvs,
+                        5 => MemoryAreaKind::Defective,
+                        _ => MemoryAreaKind::Reserved,
+                    },
+                }
+            }
+        })
+    }
+
+    pub fn available(&self) -> impl Iterator<Item = MemoryArea> {
+        self.areas()
+            .filter(|area| area.kind == MemoryAreaKind::Available)
+    }
+
+    pub fn total_available(&self) -> u64 {
+        self.available().map(|area| area.length).sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramebufferKind {
+    Indexed,
+    Rgb,
+    EgaText,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Framebuffer {
+    pub address: u64,
+    pub pitch: u32,
+    pub width: u32,
+    pub height: u32,
+    pub bpp: u8,
+    pub kind: FramebufferKind,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ElfSections {
+    count: u32,
+    entry_size: u32,
+    string_table_index: u32,
+    headers: usize,
+}
+
+impl ElfSections {
+    pub fn sections(&self) -> impl Iterator<Item = ElfSection> {
+        let (headers, entry_size) = (self.headers, self.entry_size as usize);
+        let string_table = self.header(self.string_table_index).map(|s| s.address);
+
+        (0..self.count).map(move |i| {
+            let mut section = unsafe { ElfSection::read(headers + i as usize * entry_size) };
+            section.string_table = string_table.unwrap_or(0);
+            section
+        })
+    }
+
+    fn header(&self, index: u32) -> Option<ElfSection> {
+        (index < self.count).then(|| unsafe {
+            ElfSection::read(self.headers + index as usize * self.entry_size as usize)
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ElfSection {
+    pub name_offset: u32,
+    pub kind: u32,
+    pub flags: u64,
+    pub address: u64,
+    pub size: u64,
+    string_table: u64,
+}
+
+impl ElfSection {
+    pub const FLAG_WRITE: u64 = 0x1;
+    pub const FLAG_ALLOC: u64 = 0x2;
+    pub const FLAG_EXEC: u64 = 0x4;
+
+    unsafe fn read(addr: usize) -> Self {
+        unsafe {
+            Self {
+                name_offset: read(addr),
+                kind: read(addr + 4),
+                flags: read(addr + 8),
+                address: read(addr + 16),
+                size: read(addr + 32),
+                string_table: 0,
+            }
+        }
+    }
+
+    pub fn end_address(&self) -> u64 {
+        self.address + self.size
+    }
+
+    pub fn is_allocated(&self) -> bool {
+        self.flags & Self::FLAG_ALLOC != 0
+    }
+
+    pub fn name(&self) -> &'static str {
+        if self.string_table == 0 {
+            return "";
+        }
+        // GRUB loads the (non-allocated) string table itself and reports its physical address
+        let table = if self.string_table < PHYSICAL_MEMORY_OFFSET {
+            phys_to_virt(PhysAddr::new(self.string_table)).as_u64()
+        } else {
+            self.string_table
+        };
+        let start = (table + self.name_offset as u64) as usize;
+        // section names are short, bound the scan instead of trusting the string table
+        c_str(unsafe { bytes(start, 64) })
+    }
+}
@@ -0,0 +1,215 @@
+use core::ptr;
+
+use anyhow::Result;
+use spin::Once;
+use x86_64::{
+    PhysAddr, VirtAddr,
+    instructions::{hlt, interrupts, port::Port, tables::lidt},
+    structures::DescriptorTablePointer,
+};
+
+use crate::{
+    acpi::{self, AddressSpace, dsdt::SleepType, fadt::Fadt},
+    input::{self, KeyCode, KeyEvent, Subscriber},
+    memory::paging::map_mmio,
+    sync::IrqMutex,
+    vga::println,
+};
+
+// PM1 control register bits
+const SCI_ENABLE: u16 = 1 << 0;
+const SLEEP_TYPE_SHIFT: u16 = 10;
+const SLEEP_ENABLE: u16 = 1 << 13;
+
+// how long to wait for the firmware to hand over ACPI, in polls of the control register
+const ACPI_ENABLE_POLLS: usize = 1_000_000;
+
+// emulator specific ports that power off with the given value
+const EMULATOR_SHUTDOWN_PORTS: [(u16, u16); 3] = [
+    (0x604, 0x2000),  // QEMU
+    (0xb004, 0x2000), // Bochs and older QEMU
+    (0x4004, 0x3400), // VirtualBox
+];
+
+const KEYBOARD_CONTROLLER_STATUS: u16 = 0x64;
+const KEYBOARD_CONTROLLER_INPUT_FULL: u8 = 1 << 1;
+const KEYBOARD_CONTROLLER_RESET: u8 = 0xfe;
+// how long to wait for the 8042 to take a command, and for the reset to happen, in polls
+const KEYBOARD_CONTROLLER_POLLS: usize = 1_000_000;
+
+// the reset register when it is memory mapped, mapped ahead of time since mapping takes locks the
+// interrupted code may be holding
+static RESET_REGISTER: Once<VirtAddr> = Once::new();
+
+// keeps its own subscription so the combination works whatever else is reading the keyboard
+static CTRL_ALT_DEL: IrqMutex<Option<Subscriber>> = IrqMutex::new(None);
+
+/// Maps everything `reboot` needs, so it can run from an interrupt handler.
+pub fn init() -> Result<()> {
+    if let Some(reset) = acpi::get()
+        .and_then(|acpi| acpi.fadt)
+        .and_then(|fadt| fadt.reset)
+        .filter(|reset| reset.register.space == AddressSpace::SystemMemory)
+    {
+        let addr = map_mmio(PhysAddr::new(reset.register.address), 1)?;
+        RESET_REGISTER.call_once(|| addr);
+    }
+    Ok(())
+}
+
+/// Turns the machine off through ACPI, or the shutdown ports of common emulators when that is not
+/// available. Halts if nothing worked.
+pub fn shutdown() -> ! {
+    interrupts::disable();
+
+    if let Some(acpi) = acpi::get()
+        && let (Some(fadt), Some(s5)) = (acpi.fadt, acpi.s5)
+    {
+        enter_sleep_state(&fadt, s5);
+    }
+
+    for (port, value) in EMULATOR_SHUTDOWN_PORTS {
+        unsafe { Port::new(port).write(value) };
+    }
+
+    println!("It is now safe to turn off your computer.");
+    loop {
+        hlt();
+    }
+}
+
+fn enter_sleep_state(fadt: &Fadt, sleep_type: SleepType) {
+    if fadt.pm1a_control_block == 0 {
+        return;
+    }
+    let mut pm1a = Port::<u16>::new(fadt.pm1a_control_block as u16);
+
+    // until ACPI is enabled the power management registers belong to SMM
+    if unsafe { pm1a.read() } & SCI_ENABLE == 0
+        && fadt.smi_command_port != 0
+        && fadt.acpi_enable != 0
+    {
+        unsafe { Port::new(fadt.smi_command_port as u16).write(fadt.acpi_enable) };
+        for _ in 0..ACPI_ENABLE_POLLS {
+            if unsafe { pm1a.read() } & SCI_ENABLE != 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    unsafe {
+        pm1a.write(sleep_type.a << SLEEP_TYPE_SHIFT | SLEEP_ENABLE);
+        if fadt.pm1b_control_block != 0 {
+            Port::<u16>::new(fadt.pm1b_control_block as u16)
+                .write(sleep_type.b << SLEEP_TYPE_SHIFT | SLEEP_ENABLE);
+        }
+    }
+}
+
+/// Resets the machine through the ACPI reset register, then the keyboard controller, and as a
+/// last resort a triple fault.
+pub fn reboot() -> ! {
+    interrupts::disable();
+
+    if let Some(reset) = acpi::get()
+        .and_then(|acpi| acpi.fadt)
+        .and_then(|fadt| fadt.reset)
+    {
+        match reset.register.space {
+            AddressSpace::SystemIo => unsafe {
+                Port::new(reset.register.address as u16).write(reset.value)
+            },
+            AddressSpace::SystemMemory => {
+                if let Some(addr) = RESET_REGISTER.get() {
+                    unsafe { ptr::write_volatile(addr.as_mut_ptr::<u8>(), reset.value) };
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // pulse the CPU reset line through the 8042, once it is ready for a command
+    let mut status = Port::<u8>::new(KEYBOARD_CONTROLLER_STATUS);
+    for _ in 0..KEYBOARD_CONTROLLER_POLLS {
+        if unsafe { status.read() } & KEYBOARD_CONTROLLER_INPUT_FULL == 0 {
+            break;
+        }
+        core::hint::spin_loop();
+    }
+    unsafe { status.write(KEYBOARD_CONTROLLER_RESET) };
+    for _ in 0..KEYBOARD_CONTROLLER_POLLS {
+        core::hint::spin_loop();
+    }
+
+    // with an empty IDT the breakpoint can't be delivered, and neither can the double fault
+    // that follows
+    unsafe {
+        lidt(&DescriptorTablePointer {
+            limit: 0,
+            base: VirtAddr::zero(),
+        });
+        core::arch::asm!("int3");
+    }
+    loop {
+        hlt();
+    }
+}
+
+/// Reboots on Ctrl+Alt+Del from now on.
+pub fn watch_ctrl_alt_del() {
+    *CTRL_ALT_DEL.lock() = input::subscribe();
+}
+
+/// Called from the keyboard interrupt once the key has been published.
+pub fn check_ctrl_alt_del() {
+    let Some(mut watcher) = CTRL_ALT_DEL.try_lock() else {
+        return;
+    };
+    let Some(keys) = watcher.as_mut() else {
+        return;
+    };
+
+    let mut pressed = false;
+    while let Some(event) = keys.poll() {
+        pressed |= is_ctrl_alt_del(&event);
+    }
+    if pressed {
+        reboot();
+    }
+}
+
+fn is_ctrl_alt_del(event: &KeyEvent) -> bool {
+    event.is_press()
+        && matches!(event.code, KeyCode::Delete | KeyCode::NumpadPeriod)
+        && event.modifiers.ctrl
+        && event.modifiers.alt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{KeyState, Modifiers};
+
+    #[test_case]
+    fn only_the_whole_combination_reboots() {
+        let mut event = KeyEvent {
+            code: KeyCode::Delete,
+            state: KeyState::Pressed,
+            modifiers: Modifiers {
+                ctrl: true,
+                alt: true,
+                ..Modifiers::default()
+            },
+            char: None,
+        };
+        assert!(is_ctrl_alt_del(&event));
+
+        event.modifiers.alt = false;
+        assert!(!is_ctrl_alt_del(&event));
+
+        event.modifiers.alt = true;
+        event.state = KeyState::Released;
+        assert!(!is_ctrl_alt_del(&event));
+    }
+}
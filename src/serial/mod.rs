@@ -0,0 +1,190 @@
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use lazy_static::lazy_static;
+use x86_64::instructions::port::Port;
+
+use crate::sync::IrqMutex;
+
+const COM1_BASE: u16 = 0x3f8;
+const UART_CLOCK_HZ: u32 = 115200;
+pub const DEFAULT_BAUD: u32 = 115200;
+
+const RX_BUFFER_SIZE: usize = 256;
+
+// line status register bits
+const LSR_DATA_READY: u8 = 0x01;
+const LSR_TRANSMIT_EMPTY: u8 = 0x20;
+
+static MIRROR: AtomicBool = AtomicBool::new(false);
+
+pub struct SerialPort {
+    data: Port<u8>,
+    interrupt_enable: Port<u8>,
+    fifo_control: Port<u8>,
+    line_control: Port<u8>,
+    modem_control: Port<u8>,
+    line_status: Port<u8>,
+}
+
+impl SerialPort {
+    /// # Safety
+    /// `base` must be the I/O port base of a 16550 compatible UART.
+    pub const unsafe fn new(base: u16) -> Self {
+        Self {
+            data: Port::new(base),
+            interrupt_enable: Port::new(base + 1),
+            fifo_control: Port::new(base + 2),
+            line_control: Port::new(base + 3),
+            modem_control: Port::new(base + 4),
+            line_status: Port::new(base + 5),
+        }
+    }
+
+    pub fn init(&mut self, baud: u32) {
+        let divisor = (UART_CLOCK_HZ / baud).max(1) as u16;
+
+        unsafe {
+            // disable interrupts while reprogramming
+            self.interrupt_enable.write(0x00);
+
+            // set the baud rate divisor through the DLAB latch
+            self.line_control.write(0x80);
+            self.data.write((divisor & 0xFF) as u8); // low byte
+            self.interrupt_enable.write((divisor >> 8) as u8); // high byte
+
+            // 8 data bits, no parity, one stop bit
+            self.line_control.write(0x03);
+
+            // enable and clear FIFOs, interrupt at 14 bytes
+            self.fifo_control.write(0xC7);
+
+            // DTR + RTS + OUT2, OUT2 gates the IRQ line
+            self.modem_control.write(0x0B);
+
+            // interrupt on received data
+            self.interrupt_enable.write(0x01);
+        }
+    }
+
+    pub fn send(&mut self, byte: u8) {
+        unsafe {
+            while self.line_status.read() & LSR_TRANSMIT_EMPTY == 0 {
+                core::hint::spin_loop();
+            }
+            self.data.write(byte);
+        }
+    }
+
+    pub fn try_receive(&mut self) -> Option<u8> {
+        unsafe { (self.line_status.read() & LSR_DATA_READY != 0).then(|| self.data.read()) }
+    }
+}
+
+impl fmt::Write for SerialPort {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for byte in s.bytes() {
+            if byte == b'\n' {
+                self.send(b'\r');
+            }
+            self.send(byte);
+        }
+        Ok(())
+    }
+}
+
+struct RxBuffer {
+    bytes: [u8; RX_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl RxBuffer {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; RX_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        // drop the oldest byte when nobody is reading
+        if self.len == RX_BUFFER_SIZE {
+            self.head = (self.head + 1) % RX_BUFFER_SIZE;
+            self.len -= 1;
+        }
+        self.bytes[(self.head + self.len) % RX_BUFFER_SIZE] = byte;
+        self.len += 1;
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.bytes[self.head];
+        self.head = (self.head + 1) % RX_BUFFER_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+}
+
+static RX_BUFFER: IrqMutex<RxBuffer> = IrqMutex::new(RxBuffer::new());
+
+lazy_static! {
+    pub static ref SERIAL1: IrqMutex<SerialPort> =
+        IrqMutex::new(unsafe { SerialPort::new(COM1_BASE) });
+}
+
+pub fn init(baud: u32) {
+    SERIAL1.lock().init(baud);
+}
+
+/// Makes `print!`/`println!` and the panic handler copy their output to COM1.
+pub fn set_mirror(enabled: bool) {
+    MIRROR.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_mirroring() -> bool {
+    MIRROR.load(Ordering::Relaxed)
+}
+
+/// Drains the UART FIFO into the receive buffer, called from the COM1 interrupt.
+pub fn handle_serial_interrupt() {
+    let mut serial = SERIAL1.lock();
+    let mut rx = RX_BUFFER.lock();
+    while let Some(byte) = serial.try_receive() {
+        rx.push(byte);
+    }
+}
+
+#[allow(dead_code)]
+pub fn read_byte() -> Option<u8> {
+    RX_BUFFER.lock().pop()
+}
+
+pub fn _print(args: fmt::Arguments) {
+    SERIAL1.lock().write_fmt(args).unwrap();
+}
+
+pub fn _try_print(args: fmt::Arguments) {
+    if let Some(mut serial) = SERIAL1.try_lock() {
+        serial.write_fmt(args).unwrap();
+    }
+}
+
+macro_rules! serial_print {
+    ($($arg:tt)*) => {
+        crate::serial::_print(format_args!($($arg)*))
+    };
+}
+
+macro_rules! serial_println {
+    () => (crate::serial::serial_print!("\n"));
+    ($($arg:tt)*) => (crate::serial::serial_print!("{}\n", format_args!($($arg)*)));
+}
+
+#[allow(unused_imports)]
+pub(crate) use {serial_print, serial_println};
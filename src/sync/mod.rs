@@ -0,0 +1,84 @@
+use core::{
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+};
+
+use x86_64::instructions::interrupts;
+
+pub mod ring;
+
+/// A spinlock that keeps interrupts disabled while it is held, so an interrupt handler on the
+/// same CPU can never spin on a lock the code it interrupted is holding.
+pub struct IrqMutex<T> {
+    inner: spin::Mutex<T>,
+}
+
+pub struct IrqMutexGuard<'a, T> {
+    guard: ManuallyDrop<spin::MutexGuard<'a, T>>,
+    // whether interrupts were enabled before locking
+    restore: bool,
+}
+
+impl<T> IrqMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: spin::Mutex::new(value),
+        }
+    }
+
+    pub fn lock(&self) -> IrqMutexGuard<'_, T> {
+        let restore = interrupts::are_enabled();
+        interrupts::disable();
+        IrqMutexGuard {
+            guard: ManuallyDrop::new(self.inner.lock()),
+            restore,
+        }
+    }
+
+    pub fn try_lock(&self) -> Option<IrqMutexGuard<'_, T>> {
+        let restore = interrupts::are_enabled();
+        interrupts::disable();
+        match self.inner.try_lock() {
+            Some(guard) => Some(IrqMutexGuard {
+                guard: ManuallyDrop::new(guard),
+                restore,
+            }),
+            None => {
+                if restore {
+                    interrupts::enable();
+                }
+                None
+            }
+        }
+    }
+
+    /// # Safety
+    /// Only for paths that will never return to the holder, like the panic handler.
+    pub unsafe fn force_unlock(&self) {
+        unsafe { self.inner.force_unlock() }
+    }
+}
+
+impl<T> Deref for IrqMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for IrqMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // release the lock before an interrupt gets a chance to ask for it
+        unsafe { ManuallyDrop::drop(&mut self.guard) };
+        if self.restore {
+            interrupts::enable();
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Fixed-capacity queue for exactly one producer and one consumer, typically an interrupt handler
+/// feeding the main loop. Never allocates and never blocks, a push into a full buffer is dropped
+/// and counted instead.
+pub struct RingBuffer<T: Copy, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    // both only ever grow, the slot is the index modulo N
+    head: AtomicUsize,
+    tail: AtomicUsize,
+    overflows: AtomicUsize,
+}
+
+// the producer only writes slots the consumer has released and the other way round
+unsafe impl<T: Copy + Send, const N: usize> Sync for RingBuffer<T, N> {}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    pub const fn new() -> Self {
+        Self {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            overflows: AtomicUsize::new(0),
+        }
+    }
+
+    /// Producer side. Returns `false` and bumps the overflow counter when the buffer is full.
+    pub fn push(&self, value: T) -> bool {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let head = self.head.load(Ordering::Acquire);
+        if tail.wrapping_sub(head) == N {
+            self.overflows.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        unsafe { (*self.slots[tail % N].get()).write(value) };
+        self.tail.store(tail.wrapping_add(1), Ordering::Release);
+        true
+    }
+
+    /// Consumer side.
+    pub fn pop(&self) -> Option<T> {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Acquire);
+        if head == tail {
+            return None;
+        }
+
+        let value = unsafe { (*self.slots[head % N].get()).assume_init() };
+        self.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+
+    /// Consumer side, drops everything queued so far.
+    pub fn clear(&self) {
+        let tail = self.tail.load(Ordering::Acquire);
+        self.head.store(tail, Ordering::Release);
+    }
+
+    /// Number of values dropped because the consumer fell behind.
+    #[allow(dead_code)]
+    pub fn overflows(&self) -> usize {
+        self.overflows.load(Ordering::Relaxed)
+    }
+}
+
+impl<T: Copy, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn values_come_out_in_order_across_the_wrap() {
+        let ring = RingBuffer::<u32, 4>::new();
+        for round in 0..3 {
+            for i in 0..3 {
+                assert!(ring.push(round * 10 + i));
+            }
+            for i in 0..3 {
+                assert_eq!(ring.pop(), Some(round * 10 + i));
+            }
+        }
+        assert_eq!(ring.pop(), None);
+    }
+
+    #[test_case]
+    fn full_buffer_counts_overflows() {
+        let ring = RingBuffer::<u8, 2>::new();
+        assert!(ring.push(1));
+        assert!(ring.push(2));
+        assert!(!ring.push(3));
+        assert!(!ring.push(4));
+        assert_eq!(ring.overflows(), 2);
+
+        assert_eq!(ring.pop(), Some(1));
+        assert!(ring.push(5));
+        ring.clear();
+        assert_eq!(ring.pop(), None);
+    }
+}
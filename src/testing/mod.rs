@@ -0,0 +1,81 @@
+use core::{
+    any::type_name,
+    panic::PanicInfo,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use x86_64::instructions::{hlt, port::Port};
+
+use crate::{
+    serial::{serial_print, serial_println},
+    time::{MS_PER_TICK, get_ticks},
+};
+
+// matches the `isa-debug-exit` device the runner script adds to QEMU
+const ISA_DEBUG_EXIT_PORT: u16 = 0xf4;
+
+pub const TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// tick at which the running test is considered hung
+static DEADLINE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+/// QEMU exits with `(code << 1) | 1`, so these come out as 33 and 35.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum QemuExitCode {
+    Success = 0x10,
+    Failed = 0x11,
+}
+
+pub fn exit_qemu(code: QemuExitCode) -> ! {
+    unsafe { Port::new(ISA_DEBUG_EXIT_PORT).write(code as u32) };
+
+    // not running under QEMU, or the device is missing
+    loop {
+        hlt();
+    }
+}
+
+pub trait Testable {
+    fn run(&self);
+}
+
+impl<T: Fn()> Testable for T {
+    fn run(&self) {
+        serial_print!("{}...\t", type_name::<T>());
+
+        let timeout_ticks = TEST_TIMEOUT.as_millis() as u64 / MS_PER_TICK;
+        DEADLINE.store(get_ticks() + timeout_ticks, Ordering::Relaxed);
+        self();
+        DEADLINE.store(u64::MAX, Ordering::Relaxed);
+
+        serial_println!("[ok]");
+    }
+}
+
+pub fn test_runner(tests: &[&dyn Testable]) {
+    serial_println!("Running {} tests", tests.len());
+    for test in tests {
+        test.run();
+    }
+    serial_println!("All tests passed");
+    exit_qemu(QemuExitCode::Success);
+}
+
+/// Called from the timer interrupt, fails the running test once it overruns its deadline.
+pub fn check_timeout(ticks: u64) {
+    if ticks >= DEADLINE.load(Ordering::Relaxed) {
+        DEADLINE.store(u64::MAX, Ordering::Relaxed);
+        panic!("Test timed out after {:?}", TEST_TIMEOUT);
+    }
+}
+
+pub fn panic_handler(info: &PanicInfo) -> ! {
+    x86_64::instructions::interrupts::disable();
+    unsafe { crate::serial::SERIAL1.force_unlock() };
+
+    serial_println!("[failed]");
+    serial_println!("Error: {}", info);
+    exit_qemu(QemuExitCode::Failed);
+}
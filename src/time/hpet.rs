@@ -0,0 +1,210 @@
+use core::{ptr, time::Duration};
+
+use anyhow::Result;
+use spin::Once;
+use x86_64::VirtAddr;
+
+use crate::{
+    acpi::{hpet::Hpet, madt::Madt},
+    memory::paging::map_mmio,
+};
+
+// general registers, as offsets into the event timer block
+const CAPABILITIES: usize = 0x000;
+const CONFIGURATION: usize = 0x010;
+const MAIN_COUNTER: usize = 0x0f0;
+// each comparator has its own 32 byte block of registers after the general ones
+const TIMERS: usize = 0x100;
+const TIMER_STRIDE: usize = 0x20;
+const TIMER_CONFIGURATION: usize = 0x00;
+const TIMER_COMPARATOR: usize = 0x08;
+const BLOCK_SIZE: u64 = (TIMERS + 32 * TIMER_STRIDE) as u64;
+
+const COUNTER_64BIT: u64 = 1 << 13;
+const ENABLE: u64 = 1 << 0;
+const LEGACY_REPLACEMENT: u64 = 1 << 1;
+
+const TIMER_INTERRUPT_ENABLE: u64 = 1 << 2;
+const TIMER_PERIODIC: u64 = 1 << 3;
+const TIMER_PERIODIC_CAPABLE: u64 = 1 << 4;
+const TIMER_VALUE_SET: u64 = 1 << 6;
+
+// the spec caps the counter period at 100 ns
+const MAX_PERIOD_FS: u64 = 100_000_000;
+const FS_PER_NANO: u64 = 1_000_000;
+
+// legacy replacement hardwires these, timer 0 takes over IRQ 0 from the PIT and timer 1 IRQ 8
+// from the RTC
+const PERIODIC_TIMER: usize = 0;
+const DEADLINE_TIMER: usize = 1;
+
+// set once the HPET drives the timer interrupt
+static HPET: Once<EventTimerBlock> = Once::new();
+
+/// The registers of an HPET, a free running counter with a set of comparators that raise an
+/// interrupt when it reaches them.
+pub struct EventTimerBlock {
+    base: VirtAddr,
+    // length of a counter tick, in femtoseconds
+    period: u64,
+    // 32 bit counters wrap every few minutes
+    counter_mask: u64,
+    minimum_tick: u64,
+}
+
+impl EventTimerBlock {
+    fn read(&self, register: usize) -> u64 {
+        unsafe { ptr::read_volatile((self.base.as_u64() as usize + register) as *const u64) }
+    }
+
+    fn write(&self, register: usize, value: u64) {
+        unsafe { ptr::write_volatile((self.base.as_u64() as usize + register) as *mut u64, value) }
+    }
+
+    fn timer(index: usize, register: usize) -> usize {
+        TIMERS + index * TIMER_STRIDE + register
+    }
+
+    fn counter(&self) -> u64 {
+        self.read(MAIN_COUNTER) & self.counter_mask
+    }
+
+    fn ticks_since(&self, start: u64) -> u64 {
+        self.counter().wrapping_sub(start) & self.counter_mask
+    }
+
+    /// Busy-waits for at least `duration` on the main counter, and returns the time actually
+    /// waited.
+    pub fn wait(&self, duration: Duration) -> Duration {
+        let ticks = ticks_for(duration, self.period).max(1);
+        let start = self.counter();
+
+        let mut waited = self.ticks_since(start);
+        while waited < ticks {
+            core::hint::spin_loop();
+            waited = self.ticks_since(start);
+        }
+        Duration::from_nanos((waited as u128 * self.period as u128 / FS_PER_NANO as u128) as u64)
+    }
+
+    /// Fires IRQ 0 `frequency_hz` times a second from timer 0, no faster than the firmware says
+    /// the HPET can keep up with.
+    pub fn start_periodic(&self, frequency_hz: u64) {
+        let interval = Duration::from_nanos(1_000_000_000 / frequency_hz);
+        let ticks = ticks_for(interval, self.period)
+            .max(self.minimum_tick)
+            .max(1);
+
+        // the counter is stopped while the comparator is set up, the first write sets when the
+        // first interrupt fires and the second how far apart the rest are
+        let configuration = self.read(CONFIGURATION);
+        self.write(CONFIGURATION, configuration & !ENABLE);
+        self.write(
+            Self::timer(PERIODIC_TIMER, TIMER_CONFIGURATION),
+            TIMER_INTERRUPT_ENABLE | TIMER_PERIODIC | TIMER_VALUE_SET,
+        );
+        self.write(
+            Self::timer(PERIODIC_TIMER, TIMER_COMPARATOR),
+            self.counter() + ticks,
+        );
+        self.write(Self::timer(PERIODIC_TIMER, TIMER_COMPARATOR), ticks);
+        self.write(CONFIGURATION, configuration | ENABLE);
+    }
+
+    fn set_deadline(&self, after: Duration) {
+        // the comparator may only be 32 bits wide, a later deadline just fires early and gets
+        // set again
+        let ticks = ticks_for(after, self.period).clamp(1, u32::MAX as u64);
+        self.write(
+            Self::timer(DEADLINE_TIMER, TIMER_CONFIGURATION),
+            TIMER_INTERRUPT_ENABLE,
+        );
+        self.write(
+            Self::timer(DEADLINE_TIMER, TIMER_COMPARATOR),
+            (self.counter() + ticks) & self.counter_mask,
+        );
+    }
+}
+
+/// Counter ticks in `duration`, rounded up.
+fn ticks_for(duration: Duration, period: u64) -> u64 {
+    let fs = duration.as_nanos() * FS_PER_NANO as u128;
+    fs.div_ceil(period as u128).min(u64::MAX as u128) as u64
+}
+
+/// Starts the HPET described by the ACPI tables in legacy replacement mode, with every timer
+/// masked. Nothing is touched when it fails, so the PIT keeps working.
+pub fn init(table: Option<&Hpet>, madt: Option<&Madt>) -> Result<&'static EventTimerBlock> {
+    let table = table.ok_or_else(|| anyhow::anyhow!("No HPET in the ACPI tables"))?;
+    if !table.legacy_replacement {
+        return Err(anyhow::anyhow!("HPET can't replace the PIT"));
+    }
+    // legacy replacement wires timers 0 and 1 to I/O APIC pins 2 and 8, which only line up with
+    // the routes for IRQ 0 and 8 if the MADT says they do
+    if let Some(madt) = madt
+        && (madt.isa_irq(0).gsi != 2 || madt.isa_irq(8).gsi != 8)
+    {
+        return Err(anyhow::anyhow!("IRQ 0 and 8 are not on GSI 2 and 8"));
+    }
+
+    let base = map_mmio(table.address, BLOCK_SIZE)?;
+    let mut hpet = EventTimerBlock {
+        base,
+        period: 0,
+        counter_mask: u64::MAX,
+        minimum_tick: table.minimum_tick as u64,
+    };
+
+    let capabilities = hpet.read(CAPABILITIES);
+    hpet.period = capabilities >> 32;
+    if hpet.period == 0 || hpet.period > MAX_PERIOD_FS {
+        return Err(anyhow::anyhow!("Bad HPET period of {} fs", hpet.period));
+    }
+    if capabilities & COUNTER_64BIT == 0 {
+        hpet.counter_mask = u32::MAX as u64;
+    }
+    let timers = ((capabilities >> 8) & 0x1f) as usize + 1;
+    if timers <= DEADLINE_TIMER {
+        return Err(anyhow::anyhow!("HPET has only {} timer", timers));
+    }
+    let periodic = hpet.read(EventTimerBlock::timer(PERIODIC_TIMER, TIMER_CONFIGURATION));
+    if periodic & TIMER_PERIODIC_CAPABLE == 0 {
+        return Err(anyhow::anyhow!("HPET timer 0 can't run periodically"));
+    }
+
+    hpet.write(CONFIGURATION, 0);
+    for timer in 0..timers {
+        let register = EventTimerBlock::timer(timer, TIMER_CONFIGURATION);
+        hpet.write(register, hpet.read(register) & !TIMER_INTERRUPT_ENABLE);
+    }
+    hpet.write(MAIN_COUNTER, 0);
+    hpet.write(CONFIGURATION, ENABLE | LEGACY_REPLACEMENT);
+
+    Ok(HPET.call_once(|| hpet))
+}
+
+pub fn is_enabled() -> bool {
+    HPET.get().is_some()
+}
+
+/// Raises IRQ 8 once `after` has passed, to wake a halted CPU earlier than the next tick. Does
+/// nothing without an HPET.
+pub fn set_deadline(after: Duration) {
+    if let Some(hpet) = HPET.get() {
+        hpet.set_deadline(after);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn durations_round_up_to_whole_ticks() {
+        // QEMU's HPET runs at 100 MHz, real ones are often at 14.318 MHz
+        assert_eq!(ticks_for(Duration::from_millis(1), 10_000_000), 100_000);
+        assert_eq!(ticks_for(Duration::from_millis(1), 69_841_279), 14_319);
+        assert_eq!(ticks_for(Duration::from_nanos(1), 10_000_000), 1);
+        assert_eq!(ticks_for(Duration::ZERO, 10_000_000), 0);
+    }
+}
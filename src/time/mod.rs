@@ -0,0 +1,245 @@
+use core::{
+    arch::x86_64::{__cpuid, _rdtsc},
+    fmt,
+    ops::{Add, Sub},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use x86_64::instructions::interrupts;
+
+use crate::{acpi, multiboot::BootInfo, vga::println};
+
+pub mod hpet;
+pub mod pit;
+
+pub const TIMER_FREQUENCY_HZ: u64 = 1000;
+pub const MS_PER_TICK: u64 = 1000 / TIMER_FREQUENCY_HZ;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+// each run is timed separately and the shortest wins, anything that interrupts a run only ever
+// makes it look longer
+const CALIBRATION_TIME: Duration = Duration::from_millis(10);
+const CALIBRATION_RUNS: usize = 3;
+
+// zero until the TSC is calibrated, the clock falls back to counting timer interrupts
+static TSC_HZ: AtomicU64 = AtomicU64::new(0);
+static TSC_AT_BOOT: AtomicU64 = AtomicU64::new(0);
+static TIMER_TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// A point on the monotonic clock, with nanosecond resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    // since the clock started
+    nanos: u64,
+}
+
+impl Instant {
+    pub fn now() -> Self {
+        let hz = TSC_HZ.load(Ordering::Relaxed);
+        let nanos = if hz == 0 {
+            TIMER_TICKS.load(Ordering::Relaxed) * MS_PER_TICK * 1_000_000
+        } else {
+            let cycles = unsafe { _rdtsc() }.saturating_sub(TSC_AT_BOOT.load(Ordering::Relaxed));
+            cycles_to_nanos(cycles, hz)
+        };
+        Self { nanos }
+    }
+
+    /// Time between `earlier` and this instant, zero if `earlier` is actually later.
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.nanos.saturating_sub(earlier.nanos))
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        Instant::now().duration_since(*self)
+    }
+
+    /// Time since the clock started, shortly after boot.
+    pub fn since_boot(&self) -> Duration {
+        Duration::from_nanos(self.nanos)
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant {
+            nanos: self.nanos.saturating_add(duration.as_nanos() as u64),
+        }
+    }
+}
+
+impl Sub for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+fn cycles_to_nanos(cycles: u64, hz: u64) -> u64 {
+    (cycles as u128 * NANOS_PER_SEC as u128 / hz as u128) as u64
+}
+
+fn has_tsc() -> bool {
+    // CPUID.01h:EDX bit 4
+    __cpuid(1).edx & (1 << 4) != 0
+}
+
+/// Whether the TSC ticks at the same rate through frequency changes and sleep states, without it
+/// the clock can drift on real hardware.
+pub fn tsc_is_invariant() -> bool {
+    // CPUID.80000007h:EDX bit 8, only if the extended leaf exists
+    __cpuid(0x8000_0000).eax >= 0x8000_0007 && __cpuid(0x8000_0007).edx & (1 << 8) != 0
+}
+
+/// Measures the TSC frequency with `wait`, which must busy-wait about the given time and return
+/// how long it really waited.
+pub fn calibrate_tsc(wait: impl Fn(Duration) -> Duration) -> Option<u64> {
+    if !has_tsc() {
+        return None;
+    }
+
+    (0..CALIBRATION_RUNS)
+        .map(|_| {
+            let start = unsafe { _rdtsc() };
+            let waited = wait(CALIBRATION_TIME);
+            let cycles = unsafe { _rdtsc() } - start;
+            cycles as u128 * NANOS_PER_SEC as u128 / waited.as_nanos()
+        })
+        .min()
+        .map(|hz| hz as u64)
+        .filter(|&hz| hz != 0)
+}
+
+/// What drives the timer interrupt and the TSC calibration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Pit,
+    Hpet,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Pit => write!(f, "PIT"),
+            Source::Hpet => write!(f, "HPET"),
+        }
+    }
+}
+
+/// Starts the timer interrupt off the HPET, or the PIT when there is none or `timer=pit` is on
+/// the command line, and the clock off the TSC calibrated against it. Without a TSC the clock
+/// only advances with the timer interrupt.
+pub fn init(boot_info: &BootInfo) {
+    let hpet = match boot_info.command_line_option("timer") {
+        None | Some("hpet") => {
+            let acpi = acpi::get();
+            hpet::init(
+                acpi.and_then(|acpi| acpi.hpet.as_ref()),
+                acpi.and_then(|acpi| acpi.madt.as_ref()),
+            )
+        }
+        Some("pit") => Err(anyhow::anyhow!("Disabled on the command line")),
+        Some(other) => Err(anyhow::anyhow!("Unknown timer {:?}", other)),
+    };
+
+    let tsc_hz = match hpet {
+        Ok(hpet) => {
+            hpet.start_periodic(TIMER_FREQUENCY_HZ);
+            calibrate_tsc(|duration| hpet.wait(duration))
+        }
+        Err(e) => {
+            println!("HPET unavailable, using the PIT: {}", e);
+            pit::start_periodic(TIMER_FREQUENCY_HZ);
+            calibrate_tsc(pit::wait)
+        }
+    };
+    if let Some(hz) = tsc_hz {
+        TSC_AT_BOOT.store(unsafe { _rdtsc() }, Ordering::Relaxed);
+        TSC_HZ.store(hz, Ordering::Relaxed);
+    }
+}
+
+pub fn source() -> Source {
+    if hpet::is_enabled() {
+        Source::Hpet
+    } else {
+        Source::Pit
+    }
+}
+
+/// Calibrated TSC frequency, `None` when the clock runs off the timer interrupt.
+pub fn tsc_frequency() -> Option<u64> {
+    Some(TSC_HZ.load(Ordering::Relaxed)).filter(|&hz| hz != 0)
+}
+
+/// Called from the timer interrupt.
+pub fn tick() {
+    TIMER_TICKS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Timer ticks since boot, as if counted at `TIMER_FREQUENCY_HZ`.
+pub fn get_ticks() -> u64 {
+    Instant::now().since_boot().as_millis() as u64 / MS_PER_TICK
+}
+
+/// Sleeps for at least `duration`, halting between timer interrupts, and with the HPET until the
+/// deadline itself.
+pub fn sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+
+    loop {
+        let now = Instant::now();
+        if now >= deadline {
+            break;
+        }
+
+        if interrupts::are_enabled() {
+            // a deadline that passes before the halt is caught by the next tick instead
+            hpet::set_deadline(deadline - now);
+            x86_64::instructions::hlt();
+        } else {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_case]
+    fn cycles_convert_without_overflow() {
+        assert_eq!(cycles_to_nanos(3_000_000_000, 3_000_000_000), NANOS_PER_SEC);
+        assert_eq!(cycles_to_nanos(1, 1_000_000_000), 1);
+        // a year at 5 GHz
+        let year = 365 * 24 * 3600;
+        assert_eq!(
+            cycles_to_nanos(year * 5_000_000_000, 5_000_000_000),
+            year * NANOS_PER_SEC
+        );
+    }
+
+    #[test_case]
+    fn the_clock_only_moves_forward() {
+        let start = Instant::now();
+        let mut last = start;
+        for _ in 0..1000 {
+            let now = Instant::now();
+            assert!(now >= last);
+            last = now;
+        }
+        assert_eq!(start - last, Duration::ZERO);
+    }
+
+    #[test_case]
+    fn sleep_waits_at_least_as_long_as_asked() {
+        let start = Instant::now();
+        sleep(Duration::from_millis(5));
+        assert!(start.elapsed() >= Duration::from_millis(5));
+    }
+}
@@ -0,0 +1,62 @@
+use core::time::Duration;
+
+use x86_64::instructions::port::Port;
+
+/// Input clock of the 8253/8254 programmable interval timer.
+pub const PIT_FREQUENCY_HZ: u64 = 1_193_182;
+
+const CHANNEL_0: u16 = 0x40;
+const CHANNEL_2: u16 = 0x42;
+const COMMAND: u16 = 0x43;
+// bit 0 gates channel 2, bit 1 feeds its output to the speaker, bit 5 reads that output back
+const CHANNEL_2_GATE: u16 = 0x61;
+
+const GATE_HIGH: u8 = 1 << 0;
+const SPEAKER_ON: u8 = 1 << 1;
+const CHANNEL_2_OUT: u8 = 1 << 5;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Fires IRQ 0 `frequency_hz` times a second from channel 0.
+pub fn start_periodic(frequency_hz: u64) {
+    let divisor = (PIT_FREQUENCY_HZ / frequency_hz).clamp(1, u16::MAX as u64) as u16;
+
+    let mut command_port = Port::new(COMMAND);
+    let mut data_port = Port::new(CHANNEL_0);
+    unsafe {
+        // channel 0, access mode lobyte/hibyte, mode 3 (square wave)
+        command_port.write(0x36u8);
+
+        data_port.write(divisor as u8); // low byte
+        data_port.write((divisor >> 8) as u8); // high byte
+    }
+}
+
+/// Busy-waits for about `duration`, at most around 55 ms, on channel 2, which never raises an
+/// interrupt. Returns the time actually waited, rounded to whole PIT ticks.
+pub fn wait(duration: Duration) -> Duration {
+    let count = (duration.as_nanos() * PIT_FREQUENCY_HZ as u128 / NANOS_PER_SEC as u128)
+        .clamp(1, u16::MAX as u128) as u16;
+
+    let mut gate = Port::<u8>::new(CHANNEL_2_GATE);
+    let mut command = Port::<u8>::new(COMMAND);
+    let mut data = Port::<u8>::new(CHANNEL_2);
+    unsafe {
+        let idle = gate.read() & !(GATE_HIGH | SPEAKER_ON);
+        gate.write(idle);
+
+        // channel 2, access mode lobyte/hibyte, mode 0 (interrupt on terminal count)
+        command.write(0xb0);
+        data.write(count as u8);
+        data.write((count >> 8) as u8);
+
+        // counting starts with the gate going high, the output follows once it reaches zero
+        gate.write(idle | GATE_HIGH);
+        while gate.read() & CHANNEL_2_OUT == 0 {
+            core::hint::spin_loop();
+        }
+        gate.write(idle);
+    }
+
+    Duration::from_nanos(count as u64 * NANOS_PER_SEC / PIT_FREQUENCY_HZ)
+}
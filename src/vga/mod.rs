@@ -1,9 +1,82 @@
 use core::fmt::{self, Write};
 
-const VGA_BUFFER_ADDR: usize = 0xb8000;
+const VGA_BUFFER_ADDR: u64 = 0xb8000;
 const VGA_WIDTH: usize = 80;
 const VGA_HEIGHT: usize = 25;
 
+const CURSOR_BACKGROUND: u8 = 0x10; // blue
+// blank columns between the board and the move list
+const HISTORY_GAP: usize = 7;
+const MIN_HISTORY_LINES: usize = 10;
+
+// room the board may take up, the rest of the screen holds the title, hints and the move list
+const MAX_GRID_WIDTH: usize = 56;
+const MAX_GRID_HEIGHT: usize = 15;
+
+/// How a board is laid out on screen, the first of `LAYOUTS` the board fits in is used.
+#[derive(Clone, Copy)]
+struct Layout {
+    cell_width: usize,
+    cell_height: usize,
+    // `|` and `-` lines between the cells, otherwise cells are only a column apart
+    separators: bool,
+}
+
+const LAYOUTS: [Layout; 3] = [
+    Layout {
+        cell_width: 5,
+        cell_height: 3,
+        separators: true,
+    },
+    Layout {
+        cell_width: 3,
+        cell_height: 1,
+        separators: true,
+    },
+    Layout {
+        cell_width: 1,
+        cell_height: 1,
+        separators: false,
+    },
+];
+
+impl Layout {
+    fn for_rules(rules: Rules) -> Self {
+        LAYOUTS
+            .into_iter()
+            .find(|layout| {
+                layout.grid_width(rules) <= MAX_GRID_WIDTH
+                    && layout.grid_height(rules) <= MAX_GRID_HEIGHT
+            })
+            // too big for the screen, whatever doesn't fit gets cut off
+            .unwrap_or(LAYOUTS[LAYOUTS.len() - 1])
+    }
+
+    fn col_stride(&self) -> usize {
+        self.cell_width + 1
+    }
+
+    fn row_stride(&self) -> usize {
+        self.cell_height + self.separators as usize
+    }
+
+    fn grid_width(&self, rules: Rules) -> usize {
+        rules.width * self.col_stride() - 1
+    }
+
+    fn grid_height(&self, rules: Rules) -> usize {
+        rules.height * self.row_stride() - self.separators as usize
+    }
+
+    /// Screen position of the symbol in a cell, with the grid starting at row `top`.
+    fn center(&self, top: usize, (row, col): (usize, usize)) -> (usize, usize) {
+        (
+            top + row * self.row_stride() + self.cell_height / 2,
+            col * self.col_stride() + self.cell_width / 2,
+        )
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 struct VgaChar {
@@ -26,7 +99,7 @@ pub struct VgaWriter {
 impl VgaWriter {
     unsafe fn new() -> Self {
         Self {
-            buffer: unsafe { &mut *(VGA_BUFFER_ADDR as *mut [[VgaChar; VGA_WIDTH]; VGA_HEIGHT]) },
+            buffer: unsafe { &mut *phys_to_virt(PhysAddr::new(VGA_BUFFER_ADDR)).as_mut_ptr() },
             current_row: 0,
             current_col: 0,
             color: 0x0f,
@@ -36,55 +109,48 @@ impl VgaWriter {
     pub fn draw_table(
         &mut self,
         table: &Table,
+        header: &str,
+        cursor: Option<usize>,
         errors: Vec<String>,
-        winner: Option<(Player, Win)>,
+        outcome: Outcome,
     ) {
         self.clear();
 
-        self.write_string("Tic Tac Toe\n\n");
+        let rules = table.rules();
+        self.write_string("Tic Tac Toe");
+        if rules != Rules::CLASSIC {
+            self.write_string(&format!(
+                " ({}x{}, {} in a row)",
+                rules.width, rules.height, rules.win_length
+            ));
+        }
+        self.write_string("\n");
+        self.set_color(0x07); // light gray
+        self.write_string(header);
+        self.set_color(0x0f); // reset color
+        self.write_string("\n\n");
 
         let grid_start_row = self.current_row;
+        let layout = Layout::for_rules(rules);
+        self.draw_grid(grid_start_row, table, layout);
 
-        for grid_row in 0..3 {
-            self.write_string("     |     |     \n");
-
-            for grid_col in 0..3 {
-                let index = grid_row * 3 + grid_col;
-                let ch = match table.state[index] {
-                    Some(Player::X) => {
-                        self.set_color(0x0B); // light blue
-                        b'X'
-                    }
-                    Some(Player::O) => {
-                        self.set_color(0x0E); // yellow
-                        b'O'
-                    }
-                    None => {
-                        self.set_color(0x08); // dark gray
-                        b'1' + index as u8
-                    }
-                };
-
-                self.write_string("  ");
-                self.write_byte(ch);
-                self.write_string("  ");
-                self.set_color(0x0f); // reset color
-
-                if grid_col < 2 {
-                    self.write_byte(b'|');
-                }
-            }
-            self.write_byte(b'\n');
+        if let Outcome::Win(player, win) = outcome {
+            self.draw_strikethrough(grid_start_row, layout, &win, player);
+        }
+        self.draw_history(grid_start_row, table, layout);
 
-            self.write_string("     |     |     \n");
+        self.current_row = grid_start_row + layout.grid_height(rules);
+        self.current_col = 0;
 
-            if grid_row < 2 {
-                self.write_string(" ----+-----+---- \n");
+        if let Some(cell) = cursor {
+            self.highlight_cell(grid_start_row, layout, rules.position(cell));
+            self.set_color(0x08); // dark gray
+            self.write_string("\nArrows/WASD to move, Enter/Space to place");
+            if rules.cells() <= 9 {
+                self.write_string(&format!(", 1-{} to jump", rules.cells()));
             }
-        }
-
-        if let Some((player, win)) = winner {
-            self.draw_strikethrough(grid_start_row, &win, player);
+            self.write_string("\nU/Backspace to undo, R to redo\n");
+            self.set_color(0x0f); // reset color
         }
 
         for error in errors {
@@ -93,80 +159,209 @@ impl VgaWriter {
             self.set_color(0x0f); // reset color
         }
 
-        if let Some((player, _)) = winner {
-            self.set_color(0x0A); // light green
-            match player {
-                Player::X => self.write_string("\nPlayer X wins!\n"),
-                Player::O => self.write_string("\nPlayer O wins!\n"),
+        match outcome {
+            Outcome::Win(player, _) => {
+                self.set_color(0x0A); // light green
+                match player {
+                    Player::X => self.write_string("\nPlayer X wins!\n"),
+                    Player::O => self.write_string("\nPlayer O wins!\n"),
+                }
+            }
+            Outcome::Draw => {
+                self.set_color(0x0E); // yellow
+                self.write_string("\nIt's a draw!\n");
             }
+            Outcome::InProgress => {}
+        }
+        self.set_color(0x0f); // reset color
+    }
+
+    /// Adds a line under whatever was drawn last.
+    pub fn draw_prompt(&mut self, text: &str, color: u8) {
+        self.set_color(color);
+        self.write_string(&format!("\n{}\n", text));
+        self.set_color(0x0f); // reset color
+    }
+
+    pub fn draw_menu(&mut self, title: &str, options: &[&str]) {
+        self.clear();
+
+        self.write_string(title);
+        self.write_string("\n\n");
+
+        for (index, option) in options.iter().enumerate() {
+            self.set_color(0x0B); // light blue
+            self.write_string(&format!("  {}", index + 1));
             self.set_color(0x0f); // reset color
+            self.write_string(&format!(". {}\n", option));
         }
     }
 
-    fn draw_strikethrough(&mut self, grid_start_row: usize, win: &Win, player: Player) {
-        let Win(pos1, pos2, pos3) = *win;
+    fn draw_grid(&mut self, top: usize, table: &Table, layout: Layout) {
+        let rules = table.rules();
+        let width = layout.grid_width(rules);
+
+        for row in 0..rules.height {
+            let cell_top = top + row * layout.row_stride();
+
+            for col in 0..rules.width {
+                let index = rules.index(row, col);
+                let (symbol, color) = match table.state[index] {
+                    Some(Player::X) => (b'X', 0x0B), // light blue
+                    Some(Player::O) => (b'O', 0x0E), // yellow
+                    // the cell number doubles as the key that plays there, only up to nine though
+                    None if rules.cells() <= 9 => (b'1' + index as u8, 0x08), // dark gray
+                    None => (b'.', 0x08),
+                };
 
-        let get_cell_center = |pos: usize| -> (usize, usize) {
-            let grid_row = pos / 3;
-            let grid_col = pos % 3;
-            let row = grid_start_row + grid_row * 4 + 1;
-            let col = grid_col * 6 + 2;
-            (row, col)
-        };
+                let (y, x) = layout.center(top, (row, col));
+                if let Some(ch) = self.char_at(y, x) {
+                    *ch = VgaChar::new(symbol, color);
+                }
+            }
+
+            if !layout.separators {
+                continue;
+            }
 
-        let (row1, col1) = get_cell_center(pos1);
-        let (row2, col2) = get_cell_center(pos2);
-        let (row3, col3) = get_cell_center(pos3);
+            for line in cell_top..cell_top + layout.cell_height {
+                for col in 1..rules.width {
+                    self.write_at(line, col * layout.col_stride() - 1, "|", 0x0f);
+                }
+            }
+
+            if row + 1 < rules.height {
+                let line = cell_top + layout.cell_height;
+                for x in 1..width - 1 {
+                    let ch = if (x + 1) % layout.col_stride() == 0 {
+                        "+"
+                    } else {
+                        "-"
+                    };
+                    self.write_at(line, x, ch, 0x0f);
+                }
+            }
+        }
+    }
+
+    /// Lists the moves next to the board, moves that can still be redone in gray. Only the latest
+    /// ones are shown once the list is longer than the board is tall.
+    fn draw_history(&mut self, top: usize, table: &Table, layout: Layout) {
+        let rules = table.rules();
+        let column = layout.grid_width(rules) + HISTORY_GAP;
+        let lines = layout.grid_height(rules).max(MIN_HISTORY_LINES) - 1;
+        self.write_at(top, column, "Moves", 0x0f);
+
+        let played = table.moves().iter().map(|event| (event, 0x0f));
+        let undone = table.undone().iter().rev().map(|event| (event, 0x08)); // dark gray
+        let entries = played.chain(undone).enumerate();
+        let skipped = (table.moves().len() + table.undone().len()).saturating_sub(lines);
+
+        for (line, (number, (event, color))) in entries.skip(skipped).enumerate() {
+            let player = match event.player {
+                Player::X => 'X',
+                Player::O => 'O',
+            };
+            let cell = if rules.cells() <= 9 {
+                format!("{}", event.play.0 + 1)
+            } else {
+                let (row, col) = rules.position(event.play.0);
+                format!("{},{}", row + 1, col + 1)
+            };
+            let text = format!("{}. {} on {}", number + 1, player, cell);
+            self.write_at(top + 1 + line, column, &text, color);
+        }
+    }
 
+    fn write_at(&mut self, row: usize, col: usize, text: &str, color: u8) {
+        for (offset, byte) in text.bytes().enumerate() {
+            if let Some(ch) = self.char_at(row, col + offset) {
+                *ch = VgaChar::new(byte, color);
+            }
+        }
+    }
+
+    /// `None` off screen, so boards too big for it are cut off instead of panicking.
+    fn char_at(&mut self, row: usize, col: usize) -> Option<&mut VgaChar> {
+        self.buffer.get_mut(row).and_then(|r| r.get_mut(col))
+    }
+
+    fn highlight_cell(
+        &mut self,
+        grid_start_row: usize,
+        layout: Layout,
+        (row, col): (usize, usize),
+    ) {
+        let top = grid_start_row + row * layout.row_stride();
+        let left = col * layout.col_stride();
+
+        for row in top..top + layout.cell_height {
+            for col in left..left + layout.cell_width {
+                let Some(ch) = self.char_at(row, col) else {
+                    continue;
+                };
+                // the dark gray cell number would vanish on the blue background
+                let foreground = match ch.color & 0x0f {
+                    0x08 => 0x07,
+                    foreground => foreground,
+                };
+                ch.color = CURSOR_BACKGROUND | foreground;
+            }
+        }
+    }
+
+    /// Joins the centers of the winning cells and recolors their symbols, which is all that is
+    /// left on layouts too tight to draw a line between cells.
+    fn draw_strikethrough(
+        &mut self,
+        grid_start_row: usize,
+        layout: Layout,
+        win: &Win,
+        player: Player,
+    ) {
         let strikethrough_color = match player {
             Player::X => 0x0C, // light red
             Player::O => 0x0D, // light purple
         };
 
-        // horizontal wins
-        if row1 == row2 && row2 == row3 {
-            let row = row1;
-            for col in col1..=col3 {
-                if col < VGA_WIDTH
-                    && row < VGA_HEIGHT
-                    && (col != col1 && col != col2 && col != col3)
-                {
-                    self.buffer[row][col] = VgaChar::new(b'-', strikethrough_color);
+        let centers: Vec<_> = win
+            .cells()
+            .map(|cell| layout.center(grid_start_row, cell))
+            .collect();
+
+        for pair in centers.windows(2) {
+            let ((row1, col1), (row2, col2)) = (pair[0], pair[1]);
+
+            // horizontal wins
+            if row1 == row2 {
+                for col in col1.min(col2) + 1..col1.max(col2) {
+                    self.write_at(row1, col, "-", strikethrough_color);
                 }
             }
-        }
-        // vertical wins
-        else if col1 == col2 && col2 == col3 {
-            let col = col1;
-            for row in row1..=row3 {
-                if (col < VGA_WIDTH && row < VGA_HEIGHT)
-                    && (row != row1 && row != row2 && row != row3)
-                {
-                    self.buffer[row][col] = VgaChar::new(b'|', strikethrough_color);
+            // vertical wins
+            else if col1 == col2 {
+                for row in row1 + 1..row2 {
+                    self.write_at(row, col1, "|", strikethrough_color);
                 }
             }
-        // diagonal wins (ugly, i know)
-        } else {
-            let positions = [pos1, pos2, pos3];
-            let mut sorted_positions = positions;
-            sorted_positions.sort();
-
-            if sorted_positions == [0, 4, 8] {
-                self.buffer[grid_start_row + 2][4] = VgaChar::new(b'\\', strikethrough_color);
-                self.buffer[grid_start_row + 3][5] = VgaChar::new(b'\\', strikethrough_color);
-                self.buffer[grid_start_row + 4][6] = VgaChar::new(b'\\', strikethrough_color);
-
-                self.buffer[grid_start_row + 6][10] = VgaChar::new(b'\\', strikethrough_color);
-                self.buffer[grid_start_row + 7][11] = VgaChar::new(b'\\', strikethrough_color);
-                self.buffer[grid_start_row + 8][12] = VgaChar::new(b'\\', strikethrough_color);
-            } else if sorted_positions == [2, 4, 6] {
-                self.buffer[grid_start_row + 2][12] = VgaChar::new(b'/', strikethrough_color);
-                self.buffer[grid_start_row + 3][11] = VgaChar::new(b'/', strikethrough_color);
-                self.buffer[grid_start_row + 4][10] = VgaChar::new(b'/', strikethrough_color);
-
-                self.buffer[grid_start_row + 6][6] = VgaChar::new(b'/', strikethrough_color);
-                self.buffer[grid_start_row + 7][5] = VgaChar::new(b'/', strikethrough_color);
-                self.buffer[grid_start_row + 8][4] = VgaChar::new(b'/', strikethrough_color);
+            // diagonal wins, cross the gap through the corner where the cells meet
+            else {
+                let (ch, corner, slope) = if col2 > col1 {
+                    ("\\", col1 + layout.col_stride() / 2, 1)
+                } else {
+                    ("/", col1 - layout.col_stride() / 2, -1)
+                };
+                for step in 1..layout.row_stride() {
+                    let shift = (step as isize - (layout.row_stride() / 2) as isize) * slope;
+                    let col = corner.saturating_add_signed(shift);
+                    self.write_at(row1 + step, col, ch, strikethrough_color);
+                }
+            }
+        }
+
+        for (row, col) in centers {
+            if let Some(ch) = self.char_at(row, col) {
+                ch.color = strikethrough_color;
             }
         }
     }
@@ -235,11 +430,25 @@ impl fmt::Write for VgaWriter {
 }
 
 lazy_static! {
-    pub static ref WRITER: Mutex<VgaWriter> = Mutex::new(unsafe { VgaWriter::new() });
+    pub static ref WRITER: IrqMutex<VgaWriter> = IrqMutex::new(unsafe { VgaWriter::new() });
 }
 
 pub fn _print(args: fmt::Arguments) {
     WRITER.lock().write_fmt(args).unwrap();
+    if crate::serial::is_mirroring() {
+        crate::serial::_print(args);
+    }
+}
+
+/// Like `_print`, but drops the output instead of spinning when the writer is already taken, for
+/// handlers that can fire in the middle of a `print!`.
+pub fn _try_print(args: fmt::Arguments) {
+    if let Some(mut writer) = WRITER.try_lock() {
+        writer.write_fmt(args).unwrap();
+    }
+    if crate::serial::is_mirroring() {
+        crate::serial::_try_print(args);
+    }
 }
 
 macro_rules! print {
@@ -253,13 +462,19 @@ macro_rules! println {
     ($($arg:tt)*) => (crate::vga::print!("{}\n", format_args!($($arg)*)));
 }
 
+macro_rules! try_println {
+    ($($arg:tt)*) => (crate::vga::_try_print(format_args!("{}\n", format_args!($($arg)*))));
+}
+
 use alloc::{format, string::String, vec::Vec};
 use lazy_static::lazy_static;
-use spin::Mutex;
+use x86_64::PhysAddr;
 
-use crate::game::{
+use tictactoe::{
     event::Player,
-    table::{Table, Win},
+    table::{Outcome, Rules, Table, Win},
 };
 
-pub(crate) use {print, println};
+use crate::{memory::phys_to_virt, sync::IrqMutex};
+
+pub(crate) use {print, println, try_println};
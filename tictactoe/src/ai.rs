@@ -0,0 +1,198 @@
+use alloc::vec::Vec;
+
+use crate::{
+    event::{Play, Player},
+    table::Table,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    /// Picks any free cell.
+    Easy,
+    /// Wins or blocks when it can, otherwise prefers the center and corners.
+    Medium,
+    /// Perfect play, never loses.
+    Hard,
+}
+
+/// xorshift64, good enough to keep the computer from being predictable.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    fn pick(&mut self, cells: &[usize]) -> Option<usize> {
+        (!cells.is_empty()).then(|| cells[self.below(cells.len())])
+    }
+}
+
+// rough number of positions the hard computer looks at per move, enough to solve 3x3 outright
+const SEARCH_BUDGET: usize = 1_000_000;
+
+pub fn choose_move(
+    table: &Table,
+    player: Player,
+    difficulty: Difficulty,
+    rng: &mut Rng,
+) -> Option<Play> {
+    let cell = match difficulty {
+        Difficulty::Easy => rng.pick(&free_cells(table)),
+        Difficulty::Medium => heuristic_move(table, player, rng),
+        Difficulty::Hard => best_move(table, player, rng),
+    }?;
+
+    Some(Play(cell))
+}
+
+fn free_cells(table: &Table) -> Vec<usize> {
+    (0..table.state.len())
+        .filter(|&cell| table.state[cell].is_none())
+        .collect()
+}
+
+fn winning_cell(table: &Table, player: Player) -> Option<usize> {
+    let mut board = table.clone();
+    free_cells(table).into_iter().find(|&cell| {
+        board.state[cell] = Some(player);
+        let wins = board.win_through(cell).is_some();
+        board.state[cell] = None;
+        wins
+    })
+}
+
+fn heuristic_move(table: &Table, player: Player, rng: &mut Rng) -> Option<usize> {
+    let rules = table.rules();
+    let center = rules.index(rules.height / 2, rules.width / 2);
+    let corners = [
+        0,
+        rules.width - 1,
+        rules.cells() - rules.width,
+        rules.cells() - 1,
+    ];
+
+    if let Some(cell) = winning_cell(table, player) {
+        return Some(cell);
+    }
+    if let Some(cell) = winning_cell(table, player.flip()) {
+        return Some(cell);
+    }
+    if table.state[center].is_none() {
+        return Some(center);
+    }
+
+    let free = free_cells(table);
+    let corners: Vec<usize> = free
+        .iter()
+        .copied()
+        .filter(|cell| corners.contains(cell))
+        .collect();
+    rng.pick(&corners).or_else(|| rng.pick(&free))
+}
+
+/// How many moves ahead fit in the search budget with `free` empty cells, all of them on a 3x3
+/// board, only a couple on gomoku.
+fn search_depth(free: usize) -> usize {
+    let mut depth = 1;
+    let mut positions = free;
+    while depth < free {
+        match positions.checked_mul(free - depth) {
+            Some(next) if next <= SEARCH_BUDGET => positions = next,
+            _ => break,
+        }
+        depth += 1;
+    }
+    depth
+}
+
+fn best_move(table: &Table, player: Player, rng: &mut Rng) -> Option<usize> {
+    let mut board = table.clone();
+    let free = free_cells(table);
+    let max_depth = search_depth(free.len());
+    let mut best_score = i32::MIN;
+    let mut best = Vec::new();
+
+    for cell in free {
+        board.state[cell] = Some(player);
+        let score = -negamax(
+            &mut board,
+            cell,
+            player.flip(),
+            1,
+            max_depth,
+            -i32::MAX,
+            i32::MAX,
+        );
+        board.state[cell] = None;
+
+        if score > best_score {
+            best_score = score;
+            best.clear();
+        }
+        if score == best_score {
+            best.push(cell);
+        }
+    }
+
+    // equally good moves are picked at random so games don't all look the same
+    rng.pick(&best)
+}
+
+/// Score of the position for `player` (who is about to move) after the opponent played `last`:
+/// positive is winning, faster wins and slower losses score better, beyond `max_depth` moves
+/// everything counts as even.
+fn negamax(
+    board: &mut Table,
+    last: usize,
+    player: Player,
+    depth: usize,
+    max_depth: usize,
+    mut alpha: i32,
+    beta: i32,
+) -> i32 {
+    // only the previous move can have completed a line
+    if board.win_through(last).is_some() {
+        return depth as i32 - (board.state.len() as i32 + 1);
+    }
+    if board.is_full() || depth >= max_depth {
+        return 0;
+    }
+
+    let mut best = -i32::MAX;
+    for cell in 0..board.state.len() {
+        if board.state[cell].is_some() {
+            continue;
+        }
+
+        board.state[cell] = Some(player);
+        let score = -negamax(
+            board,
+            cell,
+            player.flip(),
+            depth + 1,
+            max_depth,
+            -beta,
+            -alpha,
+        );
+        board.state[cell] = None;
+
+        best = best.max(score);
+        alpha = alpha.max(score);
+        if alpha >= beta {
+            break;
+        }
+    }
+    best
+}
@@ -0,0 +1,28 @@
+/// A move, the index of the cell in row-major order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Play(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Player {
+    X,
+    O,
+}
+impl Player {
+    pub fn flip(&self) -> Self {
+        match self {
+            Player::X => Player::O,
+            Player::O => Player::X,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Event {
+    pub play: Play,
+    pub player: Player,
+}
+impl Event {
+    pub fn new(play: Play, player: Player) -> Self {
+        Self { play, player }
+    }
+}
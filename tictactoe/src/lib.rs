@@ -0,0 +1,8 @@
+#![no_std]
+
+// game rules and the computer opponent, kept free of kernel code so the tests run on the host
+extern crate alloc;
+
+pub mod ai;
+pub mod event;
+pub mod table;
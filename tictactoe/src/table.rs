@@ -0,0 +1,490 @@
+use alloc::vec::Vec;
+
+use anyhow::Result;
+
+use crate::event::{Event, Player};
+
+// right, down, down-right and down-left, every line runs along one of these
+const DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (1, 1), (1, -1)];
+
+/// Board size and how many in a row win, an m,n,k-game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rules {
+    pub width: usize,
+    pub height: usize,
+    pub win_length: usize,
+}
+
+impl Rules {
+    pub const CLASSIC: Rules = Rules {
+        width: 3,
+        height: 3,
+        win_length: 3,
+    };
+
+    pub fn new(width: usize, height: usize, win_length: usize) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(anyhow::anyhow!("Board needs at least one cell"));
+        }
+        if win_length == 0 || win_length > width.max(height) {
+            return Err(anyhow::anyhow!(
+                "Cannot get {} in a row on a {}x{} board",
+                win_length,
+                width,
+                height
+            ));
+        }
+        Ok(Self {
+            width,
+            height,
+            win_length,
+        })
+    }
+
+    pub fn cells(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn index(&self, row: usize, col: usize) -> usize {
+        row * self.width + col
+    }
+
+    /// (row, col) of a cell index.
+    pub fn position(&self, index: usize) -> (usize, usize) {
+        (index / self.width, index % self.width)
+    }
+
+    fn step(&self, (row, col): (usize, usize), (dr, dc): (isize, isize)) -> Option<(usize, usize)> {
+        let row = row
+            .checked_add_signed(dr)
+            .filter(|&row| row < self.height)?;
+        let col = col.checked_add_signed(dc).filter(|&col| col < self.width)?;
+        Some((row, col))
+    }
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self::CLASSIC
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Table {
+    pub state: Vec<Option<Player>>,
+    rules: Rules,
+    turn: Player,
+    moves: Vec<Event>,
+    // taken back moves, the next one to redo last, forgotten once a different move is played
+    undone: Vec<Event>,
+}
+
+/// The whole winning run as (row, col) of both ends, it can be longer than the win length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Win {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+}
+
+impl Win {
+    /// (row, col) of every cell of the run, from `start` to `end`.
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize)> {
+        let (start, end) = (self.start, self.end);
+        let dr = (end.0 as isize - start.0 as isize).signum();
+        let dc = (end.1 as isize - start.1 as isize).signum();
+        let length = end.0.abs_diff(start.0).max(end.1.abs_diff(start.1)) + 1;
+
+        (0..length as isize).map(move |i| {
+            (
+                (start.0 as isize + dr * i) as usize,
+                (start.1 as isize + dc * i) as usize,
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    InProgress,
+    Win(Player, Win),
+    Draw,
+}
+
+impl Outcome {
+    pub fn is_over(&self) -> bool {
+        !matches!(self, Outcome::InProgress)
+    }
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Table {
+    /// The classic 3x3 three in a row.
+    pub fn new() -> Self {
+        Self::with_rules(Rules::CLASSIC)
+    }
+
+    pub fn with_rules(rules: Rules) -> Self {
+        Self {
+            state: alloc::vec![None; rules.cells()],
+            rules,
+            turn: Player::X,
+            moves: Vec::new(),
+            undone: Vec::new(),
+        }
+    }
+
+    /// Rebuilds a game by playing `moves` in order, failing on the first illegal one.
+    pub fn replay(rules: Rules, moves: &[Event]) -> Result<Self> {
+        let mut table = Self::with_rules(rules);
+        for &event in moves {
+            table.play(event)?;
+        }
+        Ok(table)
+    }
+
+    pub fn rules(&self) -> Rules {
+        self.rules
+    }
+
+    /// The player whose move is next, X always opens.
+    pub fn turn(&self) -> Player {
+        self.turn
+    }
+
+    pub fn play(&mut self, event: Event) -> Result<()> {
+        let index = event.play.0;
+        if self.outcome().is_over() {
+            return Err(anyhow::anyhow!("Game is already over"));
+        }
+        if event.player != self.turn {
+            return Err(anyhow::anyhow!("Not your turn"));
+        }
+        if index >= self.state.len() {
+            return Err(anyhow::anyhow!("Index out of bounds"));
+        }
+        if self.state[index].is_some() {
+            return Err(anyhow::anyhow!("Cell already occupied"));
+        }
+        self.apply(event);
+        self.undone.clear();
+        Ok(())
+    }
+
+    fn apply(&mut self, event: Event) {
+        self.state[event.play.0] = Some(event.player);
+        self.turn = event.player.flip();
+        self.moves.push(event);
+    }
+
+    /// Takes back the last move and returns it.
+    pub fn undo(&mut self) -> Option<Event> {
+        let event = self.moves.pop()?;
+        self.state[event.play.0] = None;
+        self.turn = event.player;
+        self.undone.push(event);
+        Some(event)
+    }
+
+    /// Plays the last undone move again, as long as nothing else was played since.
+    pub fn redo(&mut self) -> Option<Event> {
+        let event = self.undone.pop()?;
+        self.apply(event);
+        Some(event)
+    }
+
+    /// Moves played so far, oldest first.
+    pub fn moves(&self) -> &[Event] {
+        &self.moves
+    }
+
+    /// Moves `redo` would bring back, the next one last.
+    pub fn undone(&self) -> &[Event] {
+        &self.undone
+    }
+
+    pub fn check_wins(&self) -> Option<(Player, Win)> {
+        (0..self.state.len()).find_map(|cell| self.win_through(cell))
+    }
+
+    /// A winning run through `cell`, cheaper than `check_wins` when only one cell changed.
+    pub fn win_through(&self, cell: usize) -> Option<(Player, Win)> {
+        let player = self.state[cell]?;
+        let owned = |position: (usize, usize)| {
+            self.state[self.rules.index(position.0, position.1)] == Some(player)
+        };
+
+        for (dr, dc) in DIRECTIONS {
+            let walk = |mut position: (usize, usize), direction| {
+                let mut length = 0;
+                while let Some(next) = self.rules.step(position, direction).filter(|&p| owned(p)) {
+                    position = next;
+                    length += 1;
+                }
+                (position, length)
+            };
+
+            let origin = self.rules.position(cell);
+            let (start, behind) = walk(origin, (-dr, -dc));
+            let (end, ahead) = walk(origin, (dr, dc));
+            if behind + ahead + 1 >= self.rules.win_length {
+                return Some((player, Win { start, end }));
+            }
+        }
+
+        None
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.state.iter().all(Option::is_some)
+    }
+
+    pub fn outcome(&self) -> Outcome {
+        if let Some((player, win)) = self.check_wins() {
+            Outcome::Win(player, win)
+        } else if self.is_full() {
+            Outcome::Draw
+        } else {
+            Outcome::InProgress
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+    use crate::event::Play;
+
+    const CLASSIC_LINES: [(usize, usize, usize); 8] = [
+        // rows
+        (0, 1, 2),
+        (3, 4, 5),
+        (6, 7, 8),
+        // columns
+        (0, 3, 6),
+        (1, 4, 7),
+        (2, 5, 8),
+        // diagonals
+        (0, 4, 8),
+        (2, 4, 6),
+    ];
+
+    fn play_all(table: &mut Table, cells: &[usize]) {
+        for &cell in cells {
+            let play = Play(cell);
+            table.play(Event::new(play, table.turn())).unwrap();
+        }
+    }
+
+    /// A free cell outside `line` that doesn't win the game for whoever moves next.
+    fn harmless_cell(table: &Table, line: [usize; 3]) -> usize {
+        (0..9)
+            .find(|&cell| {
+                let mut board = table.clone();
+                board.state[cell] = Some(table.turn());
+                table.state[cell].is_none() && !line.contains(&cell) && board.check_wins().is_none()
+            })
+            .unwrap()
+    }
+
+    /// Lets `winner` fill `line` while the other player answers somewhere harmless.
+    fn complete_line(winner: Player, line: (usize, usize, usize)) -> Table {
+        let cells = [line.0, line.1, line.2];
+        let mut remaining = cells.into_iter();
+        let mut table = Table::new();
+
+        while !table.outcome().is_over() {
+            let cell = if table.turn() == winner {
+                remaining.next().unwrap()
+            } else {
+                harmless_cell(&table, cells)
+            };
+            play_all(&mut table, &[cell]);
+        }
+        table
+    }
+
+    #[test]
+    fn every_line_wins_for_both_players() {
+        let rules = Rules::CLASSIC;
+        for line in CLASSIC_LINES {
+            for winner in [Player::X, Player::O] {
+                let table = complete_line(winner, line);
+                let win = Win {
+                    start: rules.position(line.0),
+                    end: rules.position(line.2),
+                };
+                assert_eq!(table.outcome(), Outcome::Win(winner, win));
+            }
+        }
+    }
+
+    #[test]
+    fn occupied_cell_is_rejected() {
+        let mut table = Table::new();
+        play_all(&mut table, &[4]);
+
+        let err = table.play(Event::new(Play(4), Player::O)).unwrap_err();
+        assert_eq!(err.to_string(), "Cell already occupied");
+        assert_eq!(table.state[4], Some(Player::X));
+        assert_eq!(table.turn(), Player::O);
+    }
+
+    #[test]
+    fn x_moves_first() {
+        let mut table = Table::new();
+        let err = table.play(Event::new(Play(0), Player::O)).unwrap_err();
+        assert_eq!(err.to_string(), "Not your turn");
+        assert!(table.state.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn players_alternate() {
+        let mut table = Table::new();
+        table.play(Event::new(Play(0), Player::X)).unwrap();
+        assert!(table.play(Event::new(Play(1), Player::X)).is_err());
+        table.play(Event::new(Play(1), Player::O)).unwrap();
+        assert_eq!(table.turn(), Player::X);
+    }
+
+    #[test]
+    fn no_moves_after_a_win() {
+        let mut table = complete_line(Player::X, (0, 1, 2));
+        let free = table.state.iter().position(Option::is_none).unwrap();
+        let err = table
+            .play(Event::new(Play(free), table.turn()))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Game is already over");
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_history() {
+        let mut table = Table::new();
+        play_all(&mut table, &[4, 0, 8]);
+
+        assert_eq!(table.undo(), Some(Event::new(Play(8), Player::X)));
+        assert_eq!(table.undo(), Some(Event::new(Play(0), Player::O)));
+        assert_eq!(table.state[0], None);
+        assert_eq!(table.turn(), Player::O);
+        assert_eq!(table.moves(), &[Event::new(Play(4), Player::X)]);
+
+        assert_eq!(table.redo(), Some(Event::new(Play(0), Player::O)));
+        assert_eq!(table.state[0], Some(Player::O));
+        assert_eq!(table.turn(), Player::X);
+        assert_eq!(table.undone(), &[Event::new(Play(8), Player::X)]);
+    }
+
+    #[test]
+    fn new_move_forgets_undone_moves() {
+        let mut table = Table::new();
+        play_all(&mut table, &[4, 0]);
+        table.undo();
+
+        play_all(&mut table, &[2]);
+        assert!(table.undone().is_empty());
+        assert_eq!(table.redo(), None);
+    }
+
+    #[test]
+    fn undo_reopens_a_finished_game() {
+        let mut table = complete_line(Player::X, (0, 4, 8));
+        table.undo();
+        assert_eq!(table.outcome(), Outcome::InProgress);
+        assert_eq!(table.turn(), Player::X);
+    }
+
+    #[test]
+    fn replay_rebuilds_the_board() {
+        let mut table = Table::new();
+        play_all(&mut table, &[0, 1, 2, 4, 3, 5, 7, 6, 8]);
+
+        let replayed = Table::replay(Rules::CLASSIC, table.moves()).unwrap();
+        assert_eq!(replayed.state, table.state);
+        assert_eq!(replayed.moves(), table.moves());
+        assert_eq!(replayed.outcome(), Outcome::Draw);
+
+        let illegal = [
+            Event::new(Play(0), Player::X),
+            Event::new(Play(0), Player::O),
+        ];
+        assert!(Table::replay(Rules::CLASSIC, &illegal).is_err());
+    }
+
+    #[test]
+    fn full_board_without_line_is_a_draw() {
+        let mut table = Table::new();
+        // X O X / X O O / O X X
+        play_all(&mut table, &[0, 1, 2, 4, 3, 5, 7, 6, 8]);
+        assert!(table.is_full());
+        assert_eq!(table.outcome(), Outcome::Draw);
+    }
+
+    fn board(rules: Rules, x: &[(usize, usize)], o: &[(usize, usize)]) -> Table {
+        let mut table = Table::with_rules(rules);
+        for &(row, col) in x {
+            table.state[rules.index(row, col)] = Some(Player::X);
+        }
+        for &(row, col) in o {
+            table.state[rules.index(row, col)] = Some(Player::O);
+        }
+        table
+    }
+
+    #[test]
+    fn rules_must_fit_the_board() {
+        assert!(Rules::new(0, 3, 1).is_err());
+        assert!(Rules::new(4, 4, 5).is_err());
+        assert!(Rules::new(7, 6, 7).is_ok());
+    }
+
+    #[test]
+    fn connect_three_on_four_by_four() {
+        let rules = Rules::new(4, 4, 3).unwrap();
+
+        let table = board(rules, &[(1, 3), (2, 2)], &[]);
+        assert_eq!(table.outcome(), Outcome::InProgress);
+
+        let table = board(rules, &[(1, 3), (2, 2), (3, 1)], &[(0, 0)]);
+        let win = Win {
+            start: (1, 3),
+            end: (3, 1),
+        };
+        assert_eq!(table.outcome(), Outcome::Win(Player::X, win));
+        assert_eq!(win.cells().collect::<Vec<_>>(), [(1, 3), (2, 2), (3, 1)]);
+    }
+
+    #[test]
+    fn gomoku_needs_five() {
+        let rules = Rules::new(15, 15, 5).unwrap();
+        let four = [(7, 3), (7, 4), (7, 5), (7, 6)];
+
+        let table = board(rules, &four, &[(7, 2)]);
+        assert_eq!(table.outcome(), Outcome::InProgress);
+
+        let mut table = table;
+        table.state[rules.index(7, 7)] = Some(Player::X);
+        let win = Win {
+            start: (7, 3),
+            end: (7, 7),
+        };
+        assert_eq!(table.outcome(), Outcome::Win(Player::X, win));
+        assert_eq!(table.win_through(rules.index(7, 5)), Some((Player::X, win)));
+    }
+
+    #[test]
+    fn overlong_runs_are_reported_whole() {
+        let rules = Rules::new(6, 1, 3).unwrap();
+        let table = board(rules, &[(0, 1), (0, 2), (0, 3), (0, 4)], &[]);
+        let win = Win {
+            start: (0, 1),
+            end: (0, 4),
+        };
+        assert_eq!(table.outcome(), Outcome::Win(Player::X, win));
+    }
+}
@@ -0,0 +1,174 @@
+use proptest::prelude::*;
+use tictactoe::{
+    ai::{self, Difficulty, Rng},
+    event::{Event, Play, Player},
+    table::{Outcome, Rules, Table},
+};
+
+fn difficulty() -> impl Strategy<Value = Difficulty> {
+    prop_oneof![
+        Just(Difficulty::Easy),
+        Just(Difficulty::Medium),
+        Just(Difficulty::Hard),
+    ]
+}
+
+fn rules() -> impl Strategy<Value = Rules> {
+    (1..8usize, 1..8usize)
+        .prop_flat_map(|(width, height)| (Just(width), Just(height), 1..=width.max(height)))
+        .prop_map(|(width, height, win_length)| Rules::new(width, height, win_length).unwrap())
+}
+
+/// Board of random size with random stones on it, ignoring whose turn it would be.
+fn filled_board() -> impl Strategy<Value = Table> {
+    rules().prop_flat_map(|rules| {
+        prop::collection::vec(prop::option::of(any::<bool>()), rules.cells()).prop_map(
+            move |cells| {
+                let mut table = Table::with_rules(rules);
+                for (cell, stone) in cells.into_iter().enumerate() {
+                    table.state[cell] = stone.map(|x| if x { Player::X } else { Player::O });
+                }
+                table
+            },
+        )
+    })
+}
+
+/// Brute force check for `win_length` stones of `player` in a row anywhere on the board.
+fn has_line(table: &Table, player: Player) -> bool {
+    let rules = table.rules();
+    let owns = |row: isize, col: isize| {
+        (0..rules.height as isize).contains(&row)
+            && (0..rules.width as isize).contains(&col)
+            && table.state[rules.index(row as usize, col as usize)] == Some(player)
+    };
+
+    (0..rules.height as isize).any(|row| {
+        (0..rules.width as isize).any(|col| {
+            [(0, 1), (1, 0), (1, 1), (1, -1)].iter().any(|&(dr, dc)| {
+                (0..rules.win_length as isize).all(|i| owns(row + dr * i, col + dc * i))
+            })
+        })
+    })
+}
+
+fn count(table: &Table, player: Player) -> usize {
+    table
+        .state
+        .iter()
+        .filter(|&&cell| cell == Some(player))
+        .count()
+}
+
+proptest! {
+    #[test]
+    fn random_moves_keep_the_board_consistent(
+        moves in prop::collection::vec((0..9usize, any::<bool>()), 0..40),
+    ) {
+        let mut table = Table::new();
+
+        for (cell, as_x) in moves {
+            let player = if as_x { Player::X } else { Player::O };
+            let before = table.clone();
+            let result = table.play(Event::new(Play(cell), player));
+
+            let legal = !before.outcome().is_over()
+                && player == before.turn()
+                && before.state[cell].is_none();
+            prop_assert_eq!(result.is_ok(), legal);
+
+            if legal {
+                prop_assert_eq!(table.state[cell], Some(player));
+                prop_assert_eq!(table.turn(), player.flip());
+            } else {
+                // a rejected move leaves the game untouched
+                prop_assert_eq!(&table.state, &before.state);
+                prop_assert_eq!(table.turn(), before.turn());
+            }
+
+            // X opens, so it is never behind and never more than one move ahead
+            let (x, o) = (count(&table, Player::X), count(&table, Player::O));
+            prop_assert!(x == o || x == o + 1);
+        }
+    }
+
+    #[test]
+    fn outcome_matches_the_lines_on_the_board(table in filled_board()) {
+        let rules = table.rules();
+        let lines = [Player::X, Player::O].map(|player| has_line(&table, player));
+
+        match table.outcome() {
+            Outcome::Win(player, win) => {
+                prop_assert!(lines[player as usize]);
+                let cells: Vec<_> = win.cells().collect();
+                prop_assert!(cells.len() >= rules.win_length);
+                for (row, col) in cells {
+                    prop_assert_eq!(table.state[rules.index(row, col)], Some(player));
+                }
+            }
+            Outcome::Draw => prop_assert!(lines == [false, false] && table.is_full()),
+            Outcome::InProgress => prop_assert!(lines == [false, false] && !table.is_full()),
+        }
+    }
+
+    #[test]
+    fn computer_only_picks_free_cells(
+        seed in any::<u64>(),
+        rules in rules(),
+        opening in prop::collection::vec(0..49usize, 0..12),
+        difficulty in difficulty(),
+    ) {
+        let mut table = Table::with_rules(rules);
+        for cell in opening {
+            // illegal openings are just skipped
+            let _ = table.play(Event::new(Play(cell), table.turn()));
+        }
+        prop_assume!(!table.outcome().is_over());
+
+        let mut rng = Rng::new(seed);
+        let play = ai::choose_move(&table, table.turn(), difficulty, &mut rng).unwrap();
+        prop_assert!(table.state[play.0].is_none());
+    }
+
+    #[test]
+    fn hard_computer_never_loses(seed in any::<u64>(), computer_first in any::<bool>()) {
+        let computer = if computer_first { Player::X } else { Player::O };
+        let mut rng = Rng::new(seed);
+        let mut table = Table::new();
+
+        while !table.outcome().is_over() {
+            let player = table.turn();
+            let play = if player == computer {
+                ai::choose_move(&table, player, Difficulty::Hard, &mut rng)
+            } else {
+                ai::choose_move(&table, player, Difficulty::Easy, &mut rng)
+            }
+            .unwrap();
+            table.play(Event::new(play, player)).unwrap();
+        }
+
+        prop_assert!(!matches!(table.outcome(), Outcome::Win(winner, _) if winner != computer));
+    }
+
+    #[test]
+    fn history_replays_and_unwinds(cells in prop::collection::vec(0..9usize, 0..12)) {
+        let mut table = Table::new();
+        for cell in cells {
+            let _ = table.play(Event::new(Play(cell), table.turn()));
+        }
+        let played = table.moves().len();
+
+        let replayed = Table::replay(table.rules(), table.moves()).unwrap();
+        prop_assert_eq!(&replayed.state, &table.state);
+        prop_assert_eq!(replayed.turn(), table.turn());
+
+        let finished = table.state.clone();
+        while table.undo().is_some() {}
+        prop_assert!(table.state.iter().all(Option::is_none));
+        prop_assert_eq!(table.turn(), Player::X);
+
+        while table.redo().is_some() {}
+        prop_assert_eq!(table.moves().len(), played);
+        prop_assert_eq!(table.state, finished);
+    }
+}